@@ -0,0 +1,140 @@
+//! Canonical layout of generated configs, independent of struct field order and of the `toml`
+//! serializer.
+//!
+//! Crate roots come first, followed by `config`. Every other table has its keys sorted, so
+//! `global` precedes `override` and override entries are alphabetical.
+
+use anyhow::{bail, Result};
+use cairo_lang_filesystem::db::CrateIdentifier;
+use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
+use scarb_eject::{AllCratesConfig, ProjectConfigContent};
+use serde_json::{Map, Value};
+use smol_str::SmolStr;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table};
+
+/// Sort crate roots and override entries by crate identifier.
+pub fn sorted(project_config: &ProjectConfigContent) -> ProjectConfigContent {
+    fn sort<V: Clone>(
+        map: &OrderedHashMap<CrateIdentifier, V>,
+    ) -> OrderedHashMap<CrateIdentifier, V> {
+        let mut entries = map.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(id, _)| SmolStr::from((*id).clone()));
+        entries
+            .into_iter()
+            .map(|(id, value)| (id.clone(), value.clone()))
+            .collect()
+    }
+
+    ProjectConfigContent {
+        crate_roots: sort(&project_config.crate_roots),
+        crates_config: AllCratesConfig {
+            global: project_config.crates_config.global.clone(),
+            override_map: sort(&project_config.crates_config.override_map),
+        },
+    }
+}
+
+/// Render the JSON representation of a config as a TOML document in the canonical layout.
+///
+/// `null`s are left out, as the corresponding fields are optional.
+pub fn to_toml_string(value: Value) -> Result<String> {
+    let Value::Object(fields) = value else {
+        bail!("config must serialize to a table");
+    };
+
+    let mut fields = fields.into_iter().collect::<Vec<_>>();
+    fields.sort_by_key(|(key, _)| key != "crate_roots");
+
+    let mut document = DocumentMut::new();
+    for (key, value) in fields {
+        if let Some(item) = to_item(value) {
+            document.insert(&key, item);
+        }
+    }
+    Ok(document.to_string())
+}
+
+fn to_item(value: Value) -> Option<Item> {
+    match value {
+        Value::Object(fields) => Some(Item::Table(to_table(fields))),
+        value => to_value(value).map(Item::Value),
+    }
+}
+
+fn to_table(fields: Map<String, Value>) -> Table {
+    let mut table = Table::new();
+    for (key, value) in fields {
+        if let Some(item) = to_item(value) {
+            table.insert(&key, item);
+        }
+    }
+    // Headers of tables holding only other tables carry no information.
+    let only_subtables = !table.is_empty() && table.iter().all(|(_, item)| item.is_table());
+    table.set_implicit(only_subtables);
+    table
+}
+
+fn to_value(value: Value) -> Option<toml_edit::Value> {
+    let value = match value {
+        Value::Null => return None,
+        Value::Bool(value) => value.into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(number) => number.into(),
+            None => number.as_f64()?.into(),
+        },
+        Value::String(value) => value.into(),
+        Value::Array(values) => values
+            .into_iter()
+            .filter_map(to_value)
+            .collect::<Array>()
+            .into(),
+        Value::Object(fields) => fields
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, to_value(value)?)))
+            .collect::<InlineTable>()
+            .into(),
+    };
+    Some(value)
+}
+
+/// Paths of tables that deserialize to their defaults when missing, so leaving them out when
+/// they are empty does not change the meaning of the config. `*` matches any crate identifier.
+///
+/// Entries of `dependencies` are never among them, as even an empty one declares a dependency.
+const DEFAULTED_TABLES: [&[&str]; 7] = [
+    &["config"],
+    &["config", "global"],
+    &["config", "override"],
+    &["config", "global", "dependencies"],
+    &["config", "global", "experimental_features"],
+    &["config", "override", "*", "dependencies"],
+    &["config", "override", "*", "experimental_features"],
+];
+
+fn is_defaulted(path: &[&str]) -> bool {
+    DEFAULTED_TABLES.iter().any(|pattern| {
+        pattern.len() == path.len()
+            && pattern
+                .iter()
+                .zip(path)
+                .all(|(pattern, key)| *pattern == "*" || pattern == key)
+    })
+}
+
+/// Remove empty tables of [`DEFAULTED_TABLES`] from a rendered TOML config.
+pub fn strip_empty_sections(cairo_project_toml: &str) -> Result<String> {
+    fn strip(table: &mut Table, path: &[&str]) {
+        for (key, item) in table.iter_mut() {
+            if let Some(table) = item.as_table_mut() {
+                strip(table, &[path, &[key.get()]].concat());
+            }
+        }
+        table.retain(|key, item| {
+            !is_defaulted(&[path, &[key]].concat()) || item.as_table().is_none_or(|t| !t.is_empty())
+        });
+    }
+
+    let mut document = cairo_project_toml.parse::<DocumentMut>()?;
+    strip(document.as_table_mut(), &[]);
+    Ok(document.to_string())
+}
@@ -0,0 +1,342 @@
+//! Defaults of command line options, read from the nearest [`CONFIG_FILE_NAME`].
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, CommandFactory, FromArgMatches};
+use serde::{Deserialize, Serialize};
+
+use crate::Args;
+
+pub const CONFIG_FILE_NAME: &str = ".scarb-eject.toml";
+
+/// Options that can be set in [`CONFIG_FILE_NAME`], one for each long option of [`Args`].
+///
+/// Values are only checked for their type here, clap parses them like the ones given on the
+/// command line.
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigDefaults {
+    output: Option<String>,
+    output_dir: Option<PathBuf>,
+    manifest_path: Option<PathBuf>,
+    metadata_file: Option<PathBuf>,
+    metadata_cache: Option<PathBuf>,
+    offline: Option<bool>,
+    frozen: Option<bool>,
+    profile: Option<String>,
+    format: Option<String>,
+    both_profiles: Option<bool>,
+    format_with: Option<String>,
+    line_ending: Option<String>,
+    overwrite_policy: Option<String>,
+    output_encoding: Option<String>,
+    git_add: Option<bool>,
+    strip_empty_sections: Option<bool>,
+    annotate: Option<bool>,
+    with_lockfile_hash: Option<bool>,
+    check: Option<bool>,
+    diff: Option<bool>,
+    include_corelib: Option<bool>,
+    check_corelib: Option<bool>,
+    exclude_dev_deps: Option<bool>,
+    only_crate: Option<String>,
+    max_depth: Option<usize>,
+    target: Option<String>,
+    target_kind: Option<String>,
+    main_manifest: Option<PathBuf>,
+    print_selected_unit: Option<bool>,
+    inspect_crate: Option<String>,
+    explain_selection: Option<bool>,
+    print_path: Option<bool>,
+    components_report: Option<bool>,
+    merge_targets: Option<Vec<String>>,
+    all: Option<bool>,
+    per_package: Option<bool>,
+    packages_glob: Option<String>,
+    dry_run: Option<bool>,
+    backup: Option<bool>,
+    validate: Option<bool>,
+    validate_cfg: Option<bool>,
+    detect_cycles: Option<bool>,
+    crate_no_features: Option<Vec<String>>,
+    crate_edition: Option<Vec<String>>,
+    package_version: Option<String>,
+    no_version: Option<bool>,
+    warn_external_roots: Option<bool>,
+    deny_external_roots: Option<bool>,
+    deny_root_conflicts: Option<bool>,
+    strict: Option<bool>,
+    strict_edition: Option<bool>,
+    edition: Option<String>,
+    root_kind: Option<String>,
+    discriminator_strategy: Option<String>,
+    canonicalize: Option<bool>,
+    posix_paths: Option<bool>,
+    relative: Option<bool>,
+    relative_to: Option<String>,
+    cfg: Option<Vec<String>>,
+    append_cfg_from_env: Option<Vec<String>>,
+    no_experimental_features: Option<bool>,
+    corelib_path: Option<PathBuf>,
+    prune_unreferenced: Option<bool>,
+    exclude: Option<Vec<String>>,
+    include_only_target_package_roots: Option<bool>,
+    minimize: Option<bool>,
+    roots_only: Option<bool>,
+    features_override: Option<PathBuf>,
+    no_header: Option<bool>,
+    fragment: Option<bool>,
+    preserve_comments: Option<bool>,
+    self_check: Option<bool>,
+    message_format: Option<String>,
+    verbose: Option<u8>,
+    log_level: Option<String>,
+    package: Option<Vec<String>>,
+    workspace: Option<bool>,
+}
+
+/// Parse command line arguments, with defaults from the nearest [`CONFIG_FILE_NAME`].
+///
+/// Keys of the config file are long option names, with either dashes or underscores. They
+/// become defaults of the corresponding options, so anything given on the command line, or
+/// through an environment variable, replaces them. File defaults conflicting with options given
+/// on the command line are dropped, and flags set in the file can be turned off with
+/// `--flag=false`.
+pub fn parse_args() -> Result<Args> {
+    let defaults = match find_config_file() {
+        Some(config) => {
+            let contents = fs::read_to_string(&config)
+                .with_context(|| format!("failed to read {}", config.display()))?;
+            config_defaults(&contents)
+                .with_context(|| format!("failed to parse {}", config.display()))?
+        }
+        None => Vec::new(),
+    };
+    Ok(parse_with_defaults(env::args_os(), &defaults).unwrap_or_else(|err| err.exit()))
+}
+
+/// The nearest [`CONFIG_FILE_NAME`] in the current directory or its ancestors.
+fn find_config_file() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|config| config.is_file())
+}
+
+/// Argument ids with their default values, out of `contents` of a config file.
+///
+/// Flags set to `false` are left out, as that is their default anyway.
+fn config_defaults(contents: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let table = toml::from_str::<toml::Table>(contents)?
+        .into_iter()
+        .map(|(key, value)| (key.replace('-', "_"), value))
+        .collect::<toml::Table>();
+    let defaults: ConfigDefaults = toml::Value::Table(table).try_into()?;
+    let defaults = toml::Table::try_from(defaults)?;
+
+    Ok(defaults
+        .into_iter()
+        .filter(|(_, value)| *value != toml::Value::Boolean(false))
+        .map(|(key, value)| {
+            let values = match value {
+                toml::Value::Array(values) => values.into_iter().map(to_arg).collect(),
+                value => vec![to_arg(value)],
+            };
+            (key, values)
+        })
+        .collect())
+}
+
+/// Parse `args` into [`Args`], with `defaults` of [`config_defaults`] for options not given
+/// explicitly.
+fn parse_with_defaults(
+    args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+    defaults: &[(String, Vec<String>)],
+) -> Result<Args, clap::Error> {
+    let args = args.into_iter().map(Into::into).collect::<Vec<OsString>>();
+
+    // Defaults never count as conflicting, so the first pass only tells what is explicit.
+    let matches = with_defaults(Args::command(), defaults).try_get_matches_from(&args)?;
+    let command = Args::command();
+    let explicit = command
+        .get_arguments()
+        .filter(|arg| {
+            matches!(
+                matches.value_source(arg.get_id().as_str()),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .collect::<Vec<_>>();
+    let conflicts = |a: &Arg, b: &Arg| {
+        command
+            .get_arg_conflicts_with(a)
+            .iter()
+            .any(|arg| arg.get_id() == b.get_id())
+    };
+    let defaults = defaults
+        .iter()
+        .map(|(id, values)| {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id.as_str())
+                .expect("every config key is an argument");
+            let overridden = explicit.iter().any(|explicit| {
+                explicit.get_id() == arg.get_id()
+                    || conflicts(arg, explicit)
+                    || conflicts(explicit, arg)
+            });
+            let values = if overridden {
+                Vec::new()
+            } else {
+                values.clone()
+            };
+            (id.clone(), values)
+        })
+        .collect::<Vec<_>>();
+
+    let matches = with_defaults(Args::command(), &defaults).try_get_matches_from(&args)?;
+    Args::from_arg_matches(&matches)
+}
+
+/// Install `defaults` into `command`, letting flags among them take `=false`.
+///
+/// Options without values keep their own defaults, but flags still take `=false`, so that the
+/// same command line parses in both passes of [`parse_with_defaults`].
+fn with_defaults(command: clap::Command, defaults: &[(String, Vec<String>)]) -> clap::Command {
+    defaults.iter().fold(command, |command, (id, values)| {
+        command.mut_arg(id, |arg| {
+            let arg = match arg.get_action() {
+                ArgAction::SetTrue => arg
+                    .action(ArgAction::Set)
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value("true")
+                    .default_value("false"),
+                _ => arg,
+            };
+            if values.is_empty() {
+                arg
+            } else {
+                arg.default_values(values)
+            }
+        })
+    })
+}
+
+/// Format a scalar value like it would be typed on the command line, strings without quotes.
+fn to_arg(value: toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value,
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use cairo_lang_filesystem::cfg::Cfg;
+
+    use super::*;
+    use crate::{OutputFormat, OutputTarget};
+
+    fn parse(config: &str, args: &[&str]) -> Args {
+        let defaults = config_defaults(config).unwrap();
+        parse_with_defaults([&["scarb-eject"], args].concat(), &defaults).unwrap()
+    }
+
+    #[test]
+    fn every_long_option_can_be_set() {
+        for arg in Args::command().get_arguments() {
+            let id = arg.get_id().as_str();
+            if arg.get_long().is_none() || ["help", "version"].contains(&id) {
+                continue;
+            }
+            if let Err(err) = config_defaults(&format!("{id} = true")) {
+                assert!(
+                    !format!("{err:#}").contains("unknown field"),
+                    "`{id}` cannot be set in {CONFIG_FILE_NAME}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn values_are_translated_to_defaults() {
+        assert_eq!(
+            config_defaults(
+                r#"
+                include-corelib = true
+                no_header = false
+                verbose = 2
+                cfg = ["a", "b=c"]
+                max_depth = 1
+                "#
+            )
+            .unwrap(),
+            [
+                ("cfg".to_owned(), vec!["a".to_owned(), "b=c".to_owned()]),
+                ("include_corelib".to_owned(), vec!["true".to_owned()]),
+                ("max_depth".to_owned(), vec!["1".to_owned()]),
+                ("verbose".to_owned(), vec!["2".to_owned()]),
+            ]
+        );
+
+        let args = parse(
+            "include-corelib = true\nverbose = 2\ncfg = [\"a\", \"b=c\"]\nformat = \"json\"",
+            &[],
+        );
+        assert!(args.include_corelib);
+        assert_eq!(args.verbose, 2);
+        assert_eq!(args.cfg, [Cfg::name("a"), Cfg::kv("b", "c")]);
+        assert_eq!(args.format, OutputFormat::Json);
+    }
+
+    #[test]
+    fn command_line_replaces_file_defaults() {
+        let config = "format = \"json\"\nverbose = 2\ncfg = [\"a\"]";
+        let args = parse(config, &["--format", "toml", "-v", "--cfg", "b"]);
+        assert_eq!(args.format, OutputFormat::Toml);
+        assert_eq!(args.verbose, 1);
+        assert_eq!(args.cfg, [Cfg::name("b")]);
+    }
+
+    #[test]
+    fn file_defaults_conflicting_with_command_line_are_dropped() {
+        let args = parse("output = \"out.toml\"", &["--fragment"]);
+        assert!(args.fragment && args.output.is_none());
+
+        let args = parse("output = \"out.toml\"", &[]);
+        assert!(
+            matches!(args.output, Some(OutputTarget::File(path)) if path == Path::new("out.toml"))
+        );
+
+        assert!(!parse("all = true", &["-p", "a"]).all);
+        assert!(!parse("check = true", &["--diff"]).check);
+        assert!(parse("profile = \"dev\"", &["--both-profiles"])
+            .profile
+            .is_none());
+        assert!(!parse("no_version = true", &["--package-version", "1.2.3"]).no_version);
+    }
+
+    #[test]
+    fn flags_set_in_the_file_can_be_turned_off() {
+        assert!(parse("include_corelib = true", &[]).include_corelib);
+        assert!(parse("include_corelib = true", &["--include-corelib"]).include_corelib);
+        assert!(!parse("include_corelib = true", &["--include-corelib=false"]).include_corelib);
+    }
+
+    #[test]
+    fn unknown_keys_and_wrong_types_are_rejected() {
+        let err = config_defaults("no-such-option = true").unwrap_err();
+        assert!(format!("{err:#}").contains("unknown field `no_such_option`"));
+
+        let err = config_defaults("verbose = true").unwrap_err();
+        assert!(format!("{err:#}").contains("invalid type"));
+    }
+}
@@ -0,0 +1,2238 @@
+//! Create `cairo_project.toml` contents for a given Scarb package.
+//!
+//! The [`get_project_config`] function turns `scarb metadata` output into a
+//! [`ProjectConfigContent`], which can be serialized into `cairo_project.toml`.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+
+use anyhow::{Context, Result};
+use cairo_lang_filesystem::cfg::{Cfg, CfgSet};
+use cairo_lang_filesystem::db::{
+    CrateIdentifier, CrateSettings, DependencySettings, Edition, ExperimentalFeaturesConfig,
+    CORELIB_CRATE_NAME, CORELIB_VERSION,
+};
+use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
+use scarb_metadata::{
+    CompilationUnitComponentDependencyMetadata, CompilationUnitComponentMetadata,
+    CompilationUnitId, CompilationUnitMetadata, DepKind, Metadata, PackageId, PackageMetadata,
+};
+use semver::Version;
+use serde::Serialize;
+use smol_str::SmolStr;
+use tracing::warn;
+
+pub use cairo_lang_project::{AllCratesConfig, ProjectConfigContent};
+
+/// Version of the Cairo compiler crates this tool was built against.
+pub const CAIRO_VERSION: &str = CORELIB_VERSION;
+
+/// Version of the Cairo compiler crates this tool was built against, see [`CAIRO_VERSION`].
+pub fn cairo_version() -> &'static str {
+    CAIRO_VERSION
+}
+
+/// FNV-1a hash of `bytes`, which unlike `DefaultHasher` output is stable across Rust releases.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Errors returned by [`select_compilation_unit`] and [`get_project_config`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EjectError {
+    /// The main package has no compilation units at all.
+    NoCompilationUnit { package: PackageId },
+    /// No compilation unit of the main package is for [`EjectOptions::target`] and
+    /// [`EjectOptions::target_kind`].
+    ///
+    /// `available` holds names and kinds of all targets of the package.
+    TargetNotFound {
+        package: PackageId,
+        target: Option<String>,
+        kind: Option<String>,
+        available: Vec<(String, String)>,
+    },
+    /// [`EjectOptions::only_crate`] is not a component of the compilation unit.
+    CrateNotFound {
+        name: String,
+        compilation_unit: CompilationUnitId,
+    },
+    /// A component's package is missing from metadata, under [`EjectOptions::strict`].
+    PackageNotFound {
+        component: String,
+        package: PackageId,
+    },
+    /// Cfg items from metadata cannot be expressed in Cairo, under [`EjectOptions::strict`], or
+    /// some of them are lost in conversion, under [`EjectOptions::validate_cfg`].
+    CfgConversion { crate_name: String, message: String },
+    /// Two components share a crate name but not a root, under
+    /// [`EjectOptions::deny_root_conflicts`].
+    RootConflict { name: String, roots: [PathBuf; 2] },
+    /// The corelib crate root from [`EjectOptions::corelib_path`] does not exist, being a
+    /// directory or a `lib.cairo` file in it depending on `root_kind`.
+    CorelibRootNotFound { root: PathBuf, root_kind: RootKind },
+    /// Crates that would get the default edition under [`EjectOptions::strict_edition`].
+    DefaultEdition { crates: Vec<String> },
+    /// Crates with roots outside of the workspace under [`ExternalRoots::Deny`].
+    ExternalRoots {
+        workspace_root: PathBuf,
+        crates: Vec<String>,
+    },
+    /// Components depending on each other in a cycle, found with
+    /// [`EjectOptions::detect_cycles`] under [`EjectOptions::strict`].
+    ///
+    /// The cycle lists crate names, starting and ending with the same one.
+    DependencyCycle { cycle: Vec<String> },
+    /// Crate names and roots that do not exist, found with [`EjectOptions::validate`].
+    MissingCrateRoots(Vec<(SmolStr, PathBuf)>),
+}
+
+impl fmt::Display for EjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoCompilationUnit { package } => write!(
+                f,
+                "package {package} has no compilation units\n\
+                help: make sure the package defines at least one target, \
+                and try running `scarb build` first"
+            ),
+            Self::TargetNotFound {
+                package,
+                target,
+                kind,
+                available,
+            } => {
+                write!(f, "could not find a compilation unit for target")?;
+                if let Some(target) = target {
+                    write!(f, " `{target}`")?;
+                }
+                if let Some(kind) = kind {
+                    write!(f, " of kind `{kind}`")?;
+                }
+                write!(
+                    f,
+                    " in package {package}\nhelp: available targets: {}",
+                    available
+                        .iter()
+                        .map(|(name, kind)| format!("{name} ({kind})"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+            Self::CrateNotFound {
+                name,
+                compilation_unit,
+            } => write!(
+                f,
+                "crate `{name}` is not a component of compilation unit {compilation_unit}"
+            ),
+            Self::PackageNotFound { component, package } => write!(
+                f,
+                "package {package} of component {component} is missing from metadata"
+            ),
+            Self::CfgConversion {
+                crate_name,
+                message,
+            } => write!(
+                f,
+                "cfg of crate {crate_name} did not convert to a Cairo cfg set: {message}"
+            ),
+            Self::RootConflict {
+                name,
+                roots: [a, b],
+            } => write!(
+                f,
+                "crate name `{name}` maps to two different roots: {} and {}",
+                a.display(),
+                b.display()
+            ),
+            Self::CorelibRootNotFound { root, root_kind } => match root_kind {
+                RootKind::Dir => write!(f, "corelib path is not a directory: {}", root.display()),
+                RootKind::File => write!(f, "corelib root file does not exist: {}", root.display()),
+            },
+            Self::DefaultEdition { crates } => write!(
+                f,
+                "crates without an explicit, valid edition: {}",
+                crates.join(", ")
+            ),
+            Self::ExternalRoots {
+                workspace_root,
+                crates,
+            } => write!(
+                f,
+                "crates with roots outside of workspace {}: {}",
+                workspace_root.display(),
+                crates.join(", ")
+            ),
+            Self::DependencyCycle { cycle } => {
+                write!(f, "dependency cycle between crates: {}", cycle.join(" -> "))
+            }
+            Self::MissingCrateRoots(missing) => {
+                write!(f, "the following crate roots do not exist:")?;
+                for (name, root) in missing {
+                    write!(f, "\n  {name}: {}", root.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for EjectError {}
+
+/// Options controlling how the project config is generated.
+#[derive(Clone, Debug, Default)]
+pub struct EjectOptions {
+    /// Keep the corelib component in `[crate_roots]` and `[config.override]`.
+    pub include_corelib: bool,
+    /// Eject the compilation unit of this exact target name instead of picking one.
+    pub target: Option<String>,
+    /// Only consider compilation units for targets of this kind, like `lib` or `test`.
+    pub target_kind: Option<String>,
+    /// Fail if any of the generated crate roots is not an existing directory.
+    pub validate: bool,
+    /// Force this edition for the main package instead of the one from its manifest.
+    pub edition: Option<Edition>,
+    /// Emit crate roots relative to this directory instead of as absolute paths.
+    ///
+    /// Roots under neither this directory nor the workspace root, and the corelib, stay absolute.
+    pub relative_to: Option<PathBuf>,
+    /// Force editions of crates with these names, taking precedence over [`EjectOptions::edition`].
+    pub crate_editions: BTreeMap<String, Edition>,
+    /// Emit this version for the main package instead of the one from its manifest.
+    pub package_version: Option<Version>,
+    /// Leave versions out of all crate settings.
+    pub no_version: bool,
+    /// Extra cfg items added on top of the ones from metadata in the global crate settings and
+    /// in settings of crates of the main package.
+    pub extra_cfg: Vec<Cfg>,
+    /// Disable all experimental features regardless of what packages declare.
+    pub no_experimental_features: bool,
+    /// Fail if converting cfg items from metadata loses any of them.
+    pub validate_cfg: bool,
+    /// Report dependency cycles between components, as errors under [`EjectOptions::strict`].
+    pub detect_cycles: bool,
+    /// Disable all experimental features in crates with these names only.
+    pub crate_no_features: BTreeSet<String>,
+    /// Whether crate roots point at source directories or root source files.
+    pub root_kind: RootKind,
+    /// Warn if the corelib used by the compilation unit differs from [`CAIRO_VERSION`].
+    pub check_corelib: bool,
+    /// Resolve symlinks in crate roots, before making them relative.
+    pub canonicalize: bool,
+    /// Use `/` as the path separator in crate roots on all platforms.
+    pub posix_paths: bool,
+    /// Leave out dependencies that are only pulled in as dev-dependencies.
+    pub exclude_dev_deps: bool,
+    /// Only keep this crate and crates it transitively depends on.
+    pub only_crate: Option<String>,
+    /// Keep only crates at most this many dependency hops away from
+    /// [`EjectOptions::only_crate`], `0` meaning just the crate itself.
+    pub max_depth: Option<usize>,
+    /// Point the corelib crate root at this directory, or at `lib.cairo` in it under
+    /// [`RootKind::File`], instead of the corelib from metadata.
+    /// Implies keeping the corelib in `[crate_roots]`.
+    pub corelib_path: Option<PathBuf>,
+    /// Drop crates the main package does not transitively depend on.
+    pub prune_unreferenced: bool,
+    /// What to do about crate roots outside of the workspace root.
+    pub external_roots: ExternalRoots,
+    /// Fail if two components share a crate name, but have different roots.
+    pub deny_root_conflicts: bool,
+    /// Fail instead of guessing when metadata is incomplete.
+    pub strict: bool,
+    /// Fail if any crate would fall back to the default edition.
+    pub strict_edition: bool,
+    /// Drop crates with these names, along with every dependency on them.
+    pub exclude: Vec<String>,
+    /// Only keep crate roots of workspace members and the corelib, but settings of all crates.
+    pub member_roots_only: bool,
+    /// Drop per-crate settings that are identical to the global ones.
+    pub minimize: bool,
+    /// Leave crate settings at their defaults, so only `[crate_roots]` carries information.
+    pub roots_only: bool,
+    /// Force experimental features on or off in every crate, on top of what packages declare.
+    pub experimental_features_override: BTreeMap<String, bool>,
+    /// How discriminators of dependencies are emitted.
+    pub discriminator_strategy: DiscriminatorStrategy,
+}
+
+/// What crate roots in the generated config point at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RootKind {
+    /// The directory containing the crate root file.
+    #[default]
+    Dir,
+    /// The crate root file itself, usually `lib.cairo`.
+    File,
+}
+
+/// How to treat crate roots outside of the workspace root, usually from path dependencies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExternalRoots {
+    /// Keep them silently.
+    #[default]
+    Allow,
+    /// Keep them, but log a warning for each.
+    Warn,
+    /// Fail, listing all of them.
+    Deny,
+}
+
+/// How discriminators of dependencies in crate settings are emitted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiscriminatorStrategy {
+    /// Copy them from metadata as they are.
+    #[default]
+    Keep,
+    /// Leave them out, for compilers that do not understand them.
+    Drop,
+    /// Replace them with a short hash that is stable across runs and platforms.
+    Hash,
+}
+
+impl DiscriminatorStrategy {
+    fn apply(self, discriminator: Option<&str>) -> Option<SmolStr> {
+        let discriminator = discriminator?;
+        match self {
+            DiscriminatorStrategy::Keep => Some(discriminator.into()),
+            DiscriminatorStrategy::Drop => None,
+            DiscriminatorStrategy::Hash => {
+                Some(format!("{:08x}", stable_hash(discriminator.as_bytes()) as u32).into())
+            }
+        }
+    }
+}
+
+impl EjectOptions {
+    fn includes_component(&self, component: &CompilationUnitComponentMetadata) -> bool {
+        self.include_corelib || component.name != CORELIB_CRATE_NAME
+    }
+}
+
+/// Pick the compilation unit of `main_package` to eject.
+///
+/// If [`EjectOptions::target`] or [`EjectOptions::target_kind`] are set, only units matching
+/// them are considered.
+/// Among the considered units, `starknet-contract` units are preferred over `lib` ones, which are preferred over
+/// any other target.
+///
+/// Remaining ties are broken by target name and then by compilation unit id, so the pick does
+/// not depend on the order of units in metadata.
+pub fn select_compilation_unit<'a>(
+    metadata: &'a Metadata,
+    main_package: &PackageMetadata,
+    options: &EjectOptions,
+) -> Result<&'a CompilationUnitMetadata, EjectError> {
+    let units = metadata
+        .compilation_units
+        .iter()
+        .filter(|unit| unit.package == main_package.id)
+        .collect::<Vec<_>>();
+
+    if units.is_empty() {
+        return Err(EjectError::NoCompilationUnit {
+            package: main_package.id.clone(),
+        });
+    }
+
+    units
+        .iter()
+        .copied()
+        .filter(|unit| {
+            options
+                .target
+                .as_ref()
+                .is_none_or(|target| &unit.target.name == target)
+        })
+        .filter(|unit| {
+            options
+                .target_kind
+                .as_ref()
+                .is_none_or(|kind| &unit.target.kind == kind)
+        })
+        .min_by_key(|unit| {
+            (
+                selection_priority(unit),
+                unit.target.name.as_str(),
+                &unit.id,
+            )
+        })
+        .ok_or_else(|| {
+            if options.target.is_none() && options.target_kind.is_none() {
+                return EjectError::NoCompilationUnit {
+                    package: main_package.id.clone(),
+                };
+            }
+            EjectError::TargetNotFound {
+                package: main_package.id.clone(),
+                target: options.target.clone(),
+                kind: options.target_kind.clone(),
+                available: units
+                    .iter()
+                    .map(|unit| (unit.target.name.clone(), unit.target.kind.clone()))
+                    .collect(),
+            }
+        })
+}
+
+/// Rank of `unit` in [`select_compilation_unit`], lower ones being preferred.
+pub fn selection_priority(unit: &CompilationUnitMetadata) -> u8 {
+    match unit.target.name.as_str() {
+        "starknet-contract" => 0,
+        "lib" => 1,
+        _ => 2,
+    }
+}
+
+/// Generate [`ProjectConfigContent`] for the most suitable compilation unit of `main_package`.
+pub fn get_project_config(
+    metadata: &Metadata,
+    main_package: &PackageMetadata,
+    options: &EjectOptions,
+) -> Result<ProjectConfigContent, EjectError> {
+    let compilation_unit = select_compilation_unit(metadata, main_package, options)?;
+
+    check_duplicate_crate_names(compilation_unit, options)?;
+
+    if options.check_corelib {
+        check_corelib_version(metadata, compilation_unit);
+    }
+
+    for name in options.crate_editions.keys() {
+        if !compilation_unit.components.iter().any(|c| c.name == *name) {
+            warn!(
+                "crate `{name}` with an edition override is not a component of compilation unit {}",
+                compilation_unit.id
+            );
+        }
+    }
+    for name in &options.crate_no_features {
+        if !compilation_unit.components.iter().any(|c| c.name == *name) {
+            warn!(
+                "crate `{name}` with disabled features is not a component of compilation unit {}",
+                compilation_unit.id
+            );
+        }
+    }
+
+    if options.external_roots != ExternalRoots::Allow {
+        check_external_roots(metadata, compilation_unit, options)?;
+    }
+
+    if options.strict {
+        if let Some(component) = compilation_unit
+            .components
+            .iter()
+            .filter(|c| options.includes_component(c))
+            .find(|c| metadata.get_package(&c.package).is_none())
+        {
+            return Err(EjectError::PackageNotFound {
+                component: component.name.clone(),
+                package: component.package.clone(),
+            });
+        }
+    }
+
+    if options.strict || options.validate_cfg {
+        let cfg_sets = compilation_unit
+            .components
+            .iter()
+            .filter_map(|c| Some((c.name.as_str(), c.cfg.as_deref()?)))
+            .chain([(main_package.name.as_str(), compilation_unit.cfg.as_slice())]);
+        for (crate_name, cfg_set) in cfg_sets {
+            let converted = convert_cfg_set(cfg_set).map_err(|err| EjectError::CfgConversion {
+                crate_name: crate_name.to_owned(),
+                message: err.to_string(),
+            })?;
+            if options.validate_cfg {
+                let items = match serde_json::to_value(&converted) {
+                    Ok(serde_json::Value::Array(items)) => items.len(),
+                    _ => 0,
+                };
+                if items != cfg_set.len() {
+                    return Err(EjectError::CfgConversion {
+                        crate_name: crate_name.to_owned(),
+                        message: format!("{items} of {} cfg items survived", cfg_set.len()),
+                    });
+                }
+            }
+        }
+    }
+
+    if options.detect_cycles {
+        for cycle in dependency_cycles(compilation_unit) {
+            if options.strict {
+                return Err(EjectError::DependencyCycle { cycle });
+            }
+            warn!("dependency cycle between crates: {}", cycle.join(" -> "));
+        }
+    }
+
+    if options.strict_edition {
+        check_strict_editions(metadata, compilation_unit, options)?;
+    }
+
+    let mut crate_roots = get_crate_roots(metadata, compilation_unit, options);
+
+    if let Some(corelib_path) = &options.corelib_path {
+        let (root, exists) = match options.root_kind {
+            RootKind::Dir => (corelib_path.clone(), corelib_path.is_dir()),
+            RootKind::File => {
+                let root = corelib_path.join("lib.cairo");
+                let exists = root.is_file();
+                (root, exists)
+            }
+        };
+        if !exists {
+            return Err(EjectError::CorelibRootNotFound {
+                root,
+                root_kind: options.root_kind,
+            });
+        }
+        let corelib = compilation_unit
+            .components
+            .iter()
+            .find(|c| c.name == CORELIB_CRATE_NAME)
+            .map(crate_identifier)
+            .unwrap_or(CORELIB_CRATE_NAME.into());
+        crate_roots.insert(corelib, root);
+    }
+    let mut crates_config = get_crates_config(metadata, compilation_unit, main_package, options);
+
+    if let Some(only_crate) = &options.only_crate {
+        let root = compilation_unit
+            .components
+            .iter()
+            .find(|c| c.name == *only_crate)
+            .ok_or_else(|| EjectError::CrateNotFound {
+                name: only_crate.clone(),
+                compilation_unit: compilation_unit.id.clone(),
+            })?;
+        let reachable = dependency_closure(compilation_unit, root, options.max_depth);
+        retain_crates(&mut crate_roots, &mut crates_config, &reachable);
+    }
+
+    if options.prune_unreferenced {
+        if compilation_unit
+            .components
+            .iter()
+            .any(|c| c.dependencies.is_none())
+        {
+            warn!(
+                "metadata does not list component dependencies, \
+                not pruning unreferenced crates of compilation unit {}",
+                compilation_unit.id
+            );
+        } else {
+            let reachable = compilation_unit
+                .components
+                .iter()
+                .filter(|c| c.package == compilation_unit.package)
+                .flat_map(|root| dependency_closure(compilation_unit, root, None))
+                .collect::<Vec<_>>();
+            retain_crates(&mut crate_roots, &mut crates_config, &reachable);
+        }
+    }
+
+    if !options.exclude.is_empty() {
+        exclude_crates(
+            compilation_unit,
+            &mut crate_roots,
+            &mut crates_config,
+            options,
+        );
+    }
+
+    if options.member_roots_only {
+        let members = compilation_unit
+            .components
+            .iter()
+            .filter(|c| {
+                c.name == CORELIB_CRATE_NAME || metadata.workspace.members.contains(&c.package)
+            })
+            .map(crate_identifier)
+            .collect::<HashSet<_>>();
+        crate_roots.retain(|id, _| members.contains(id));
+    }
+
+    if options.minimize {
+        remove_redundant_overrides(&mut crates_config);
+    }
+
+    if options.roots_only {
+        crates_config = AllCratesConfig::default();
+    }
+
+    let project_config = ProjectConfigContent {
+        crate_roots,
+        crates_config,
+    };
+
+    if options.validate {
+        validate_crate_roots(&project_config, options)?;
+    }
+
+    Ok(project_config)
+}
+
+/// Drop `[config.override]` entries that resolve to exactly the global settings.
+///
+/// Cairo falls back to global settings only for crates without an override entry at all, so
+/// entries are removed as a whole, never field by field. An entry is only redundant if its crate
+/// would keep its name, that is if `name` is unset or equal to the crate identifier.
+fn remove_redundant_overrides(crates_config: &mut AllCratesConfig) {
+    let global = crates_config.global.clone();
+    crates_config.override_map.retain(|id, settings| {
+        let name_from_id = settings
+            .name
+            .as_ref()
+            .is_none_or(|name| *name == SmolStr::from(id.clone()));
+        let redundant = name_from_id
+            && CrateSettings {
+                name: None,
+                ..settings.clone()
+            } == global;
+        !redundant
+    });
+}
+
+/// Merge the config of another compilation unit into `config`.
+///
+/// Crate roots and per-crate settings are unioned. Where both configs have different values for
+/// the same crate, or different global settings, the ones from `other` win and a warning is
+/// logged.
+pub fn merge_project_config(config: &mut ProjectConfigContent, other: ProjectConfigContent) {
+    for (id, root) in other.crate_roots {
+        if let Some(existing) = config.crate_roots.get(&id).filter(|r| **r != root) {
+            warn!(
+                "conflicting crate roots for crate `{}`: {} and {}, using the latter",
+                SmolStr::from(id.clone()),
+                existing.display(),
+                root.display()
+            );
+        }
+        config.crate_roots.insert(id, root);
+    }
+
+    for (id, settings) in other.crates_config.override_map {
+        if config
+            .crates_config
+            .override_map
+            .get(&id)
+            .is_some_and(|s| *s != settings)
+        {
+            warn!(
+                "conflicting settings for crate `{}`, using the latter",
+                SmolStr::from(id.clone())
+            );
+        }
+        config.crates_config.override_map.insert(id, settings);
+    }
+
+    if config.crates_config.global != other.crates_config.global {
+        warn!("conflicting global crate settings, using the latter");
+        config.crates_config.global = other.crates_config.global;
+    }
+}
+
+/// Remove crates named in [`EjectOptions::exclude`] from crate roots, crate settings and every
+/// `dependencies` map.
+fn exclude_crates(
+    compilation_unit: &CompilationUnitMetadata,
+    crate_roots: &mut OrderedHashMap<CrateIdentifier, PathBuf>,
+    crates_config: &mut AllCratesConfig,
+    options: &EjectOptions,
+) {
+    let mut excluded = HashSet::new();
+    for name in &options.exclude {
+        let components = compilation_unit
+            .components
+            .iter()
+            .filter(|c| c.name == *name)
+            .map(crate_identifier)
+            .collect::<Vec<_>>();
+        if components.is_empty() {
+            warn!(
+                "excluded crate `{name}` is not a component of compilation unit {}",
+                compilation_unit.id
+            );
+        }
+        excluded.extend(components);
+    }
+
+    crate_roots.retain(|id, _| !excluded.contains(id));
+    crates_config
+        .override_map
+        .retain(|id, _| !excluded.contains(id));
+
+    let overrides = crates_config
+        .override_map
+        .iter_mut()
+        .map(|(_, settings)| settings);
+    for settings in std::iter::once(&mut crates_config.global).chain(overrides) {
+        settings
+            .dependencies
+            .retain(|name, _| !options.exclude.contains(name));
+    }
+}
+
+/// Keep only crate roots, crate settings and dependencies, global or per-crate, of crates in
+/// `keep`.
+fn retain_crates(
+    crate_roots: &mut OrderedHashMap<CrateIdentifier, PathBuf>,
+    crates_config: &mut AllCratesConfig,
+    keep: &[&CompilationUnitComponentMetadata],
+) {
+    let ids = keep
+        .iter()
+        .map(|c| crate_identifier(c))
+        .collect::<HashSet<_>>();
+    crate_roots.retain(|id, _| ids.contains(id));
+    crates_config.override_map.retain(|id, _| ids.contains(id));
+
+    let overrides = crates_config
+        .override_map
+        .iter_mut()
+        .map(|(_, settings)| settings);
+    for settings in std::iter::once(&mut crates_config.global).chain(overrides) {
+        settings
+            .dependencies
+            .retain(|name, _| keep.iter().any(|c| c.name == *name));
+    }
+}
+
+/// Collect `root` and all components it transitively depends on, at most `max_depth` hops away.
+///
+/// Each component is collected once, so dependency cycles do not make this loop.
+fn dependency_closure<'a>(
+    compilation_unit: &'a CompilationUnitMetadata,
+    root: &'a CompilationUnitComponentMetadata,
+    max_depth: Option<usize>,
+) -> Vec<&'a CompilationUnitComponentMetadata> {
+    let mut reachable = vec![root];
+    // Components are collected breadth-first, so depths never decrease along `reachable`.
+    let mut depths = vec![0];
+    let mut i = 0;
+    while let Some(component) = reachable.get(i).copied() {
+        let depth = depths[i];
+        i += 1;
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            continue;
+        }
+        for CompilationUnitComponentDependencyMetadata { id, .. } in
+            component.dependencies.iter().flatten()
+        {
+            if let Some(dependency) = compilation_unit
+                .components
+                .iter()
+                .find(|c| c.id.as_ref() == Some(id))
+            {
+                if !reachable.iter().any(|c| c.id == dependency.id) {
+                    reachable.push(dependency);
+                    depths.push(depth + 1);
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// Find dependency cycles between components, each as crate names from one back to itself.
+fn dependency_cycles(compilation_unit: &CompilationUnitMetadata) -> Vec<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    fn visit(
+        compilation_unit: &CompilationUnitMetadata,
+        index: usize,
+        states: &mut [State],
+        path: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        states[index] = State::InProgress;
+        path.push(index);
+        let components = &compilation_unit.components;
+        for CompilationUnitComponentDependencyMetadata { id, .. } in
+            components[index].dependencies.iter().flatten()
+        {
+            let Some(dependency) = components.iter().position(|c| c.id.as_ref() == Some(id)) else {
+                continue;
+            };
+            match states[dependency] {
+                State::Unvisited => visit(compilation_unit, dependency, states, path, cycles),
+                State::InProgress => {
+                    let start = path.iter().position(|&i| i == dependency).unwrap();
+                    cycles.push(
+                        path[start..]
+                            .iter()
+                            .chain([&dependency])
+                            .map(|&i| components[i].name.clone())
+                            .collect(),
+                    );
+                }
+                State::Done => {}
+            }
+        }
+        path.pop();
+        states[index] = State::Done;
+    }
+
+    let mut states = vec![State::Unvisited; compilation_unit.components.len()];
+    let mut cycles = Vec::new();
+    for index in 0..compilation_unit.components.len() {
+        if states[index] == State::Unvisited {
+            visit(
+                compilation_unit,
+                index,
+                &mut states,
+                &mut Vec::new(),
+                &mut cycles,
+            );
+        }
+    }
+    cycles
+}
+
+/// Warn if the corelib of the compilation unit is not the one this tool was built against.
+fn check_corelib_version(metadata: &Metadata, compilation_unit: &CompilationUnitMetadata) {
+    let Some(corelib) = compilation_unit
+        .components
+        .iter()
+        .find(|c| c.name == CORELIB_CRATE_NAME)
+    else {
+        warn!(
+            "compilation unit {} has no corelib component",
+            compilation_unit.id
+        );
+        return;
+    };
+
+    match metadata.get_package(&corelib.package) {
+        Some(package) if package.version.to_string() != CAIRO_VERSION => warn!(
+            "corelib version {} differs from Cairo version {CAIRO_VERSION} \
+            scarb-eject was built with, the ejected config may not compile",
+            package.version
+        ),
+        Some(_) => {}
+        None => warn!("could not find corelib package {}", corelib.package),
+    }
+}
+
+/// Warn about distinct components sharing a crate name, as only one of them will be usable.
+///
+/// With [`EjectOptions::deny_root_conflicts`], such components with different roots are an error.
+fn check_duplicate_crate_names(
+    compilation_unit: &CompilationUnitMetadata,
+    options: &EjectOptions,
+) -> Result<(), EjectError> {
+    let components = compilation_unit
+        .components
+        .iter()
+        .filter(|c| options.includes_component(c))
+        .collect::<Vec<_>>();
+
+    for (i, a) in components.iter().enumerate() {
+        for b in &components[i + 1..] {
+            if a.name == b.name && a.id != b.id {
+                if options.deny_root_conflicts && a.source_root() != b.source_root() {
+                    return Err(EjectError::RootConflict {
+                        name: a.name.clone(),
+                        roots: [
+                            a.source_root().to_path_buf().into_std_path_buf(),
+                            b.source_root().to_path_buf().into_std_path_buf(),
+                        ],
+                    });
+                }
+                warn!(
+                    "crate name `{}` is used by multiple components: {} at {} and {} at {}",
+                    a.name,
+                    a.package,
+                    a.source_root(),
+                    b.package,
+                    b.source_root()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that every crate root points at an existing directory or file, depending on
+/// [`EjectOptions::root_kind`], reporting all missing ones.
+///
+/// Relative crate roots are resolved against [`EjectOptions::relative_to`].
+fn validate_crate_roots(
+    project_config: &ProjectConfigContent,
+    options: &EjectOptions,
+) -> Result<(), EjectError> {
+    let missing = project_config
+        .crate_roots
+        .iter()
+        .filter(|(_, root)| {
+            let root = match &options.relative_to {
+                Some(base) => base.join(root),
+                None => root.to_path_buf(),
+            };
+            match options.root_kind {
+                RootKind::Dir => !root.is_dir(),
+                RootKind::File => !root.is_file(),
+            }
+        })
+        .map(|(id, root)| {
+            let name = project_config
+                .crates_config
+                .override_map
+                .get(id)
+                .and_then(|settings| settings.name.clone())
+                .unwrap_or_else(|| SmolStr::from(id.clone()));
+            (name, root.clone())
+        })
+        .collect::<Vec<_>>();
+
+    if !missing.is_empty() {
+        return Err(EjectError::MissingCrateRoots(missing));
+    }
+
+    Ok(())
+}
+
+/// Collect `[crate_roots]` for all components of the compilation unit.
+///
+/// Corelib is skipped unless [`EjectOptions::include_corelib`] is set.
+pub fn get_crate_roots(
+    metadata: &Metadata,
+    compilation_unit: &CompilationUnitMetadata,
+    options: &EjectOptions,
+) -> OrderedHashMap<CrateIdentifier, PathBuf> {
+    // Roots elsewhere, like in the Scarb cache, are better left absolute than climbed up to.
+    let is_in_tree = |root: &Path, base: &Path| {
+        let root = std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+        std::path::absolute(base).is_ok_and(|base| root.starts_with(base))
+            || root.starts_with(metadata.workspace.root.as_std_path())
+    };
+
+    compilation_unit
+        .components
+        .iter()
+        .filter(|c| options.includes_component(c))
+        .map(|c| {
+            let root = match options.root_kind {
+                RootKind::Dir => c.source_root(),
+                RootKind::File => &c.source_path,
+            };
+            // Source roots of some path dependencies may be relative to the workspace root.
+            let root = metadata.workspace.root.join(root).into_std_path_buf();
+            let root = if options.canonicalize {
+                fs::canonicalize(&root).unwrap_or_else(|err| {
+                    warn!(
+                        "cannot canonicalize crate root of {}, keeping {}: {err}",
+                        c.name,
+                        root.display()
+                    );
+                    root
+                })
+            } else {
+                root
+            };
+            let root = match &options.relative_to {
+                Some(base) if c.name != CORELIB_CRATE_NAME && !is_in_tree(&root, base) => {
+                    warn!(
+                        "crate root of {} is outside of {} and of the workspace, keeping it absolute",
+                        c.name,
+                        base.display()
+                    );
+                    root
+                }
+                Some(base) if c.name != CORELIB_CRATE_NAME => relative_path(&root, base)
+                    .unwrap_or_else(|| {
+                        warn!(
+                            "cannot express crate root of {} relative to {}, keeping it absolute",
+                            c.name,
+                            base.display()
+                        );
+                        root
+                    }),
+                Some(_) => {
+                    warn!("keeping corelib crate root absolute");
+                    root
+                }
+                None => root,
+            };
+            let root = if options.posix_paths {
+                to_posix_path(root)
+            } else {
+                root
+            };
+            (crate_identifier(c), root)
+        })
+        .collect()
+}
+
+/// Use `/` as the path separator, so configs generated on different platforms are identical.
+fn to_posix_path(path: PathBuf) -> PathBuf {
+    if MAIN_SEPARATOR == '/' {
+        return path;
+    }
+
+    match path.to_str() {
+        Some(path) => path.replace(MAIN_SEPARATOR, "/").into(),
+        None => path,
+    }
+}
+
+/// Compute `path` relative to the `base` directory, adding `..` segments where needed.
+///
+/// Returns [`None`] if both paths do not share the same root, e.g. are on different drives.
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    let base = std::path::absolute(base).ok()?;
+
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+
+    match (path_components.peek(), base_components.peek()) {
+        (Some(Component::Prefix(a)), Some(Component::Prefix(b))) if a != b => return None,
+        (Some(Component::RootDir), Some(Component::RootDir)) => {}
+        (Some(Component::Prefix(_)), Some(Component::Prefix(_))) => {}
+        _ => return None,
+    }
+
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+
+    let relative = base_components
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|_| Component::ParentDir)
+        .chain(path_components)
+        .collect::<PathBuf>();
+
+    if relative.as_os_str().is_empty() {
+        Some(PathBuf::from("."))
+    } else {
+        Some(relative)
+    }
+}
+
+/// Collect `[config]` settings for all components of the compilation unit.
+///
+/// Corelib is skipped unless [`EjectOptions::include_corelib`] is set.
+pub fn get_crates_config(
+    metadata: &Metadata,
+    compilation_unit: &CompilationUnitMetadata,
+    main_package: &PackageMetadata,
+    options: &EjectOptions,
+) -> AllCratesConfig {
+    let global_crate_settings =
+        get_global_crate_settings(metadata, compilation_unit, main_package, options);
+    let mut components = compilation_unit
+        .components
+        .iter()
+        .filter(|c| options.includes_component(c))
+        .collect::<Vec<_>>();
+    // Metadata order may differ between Scarb versions, sort to keep the output reproducible.
+    components.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+
+    let override_map = components
+        .into_iter()
+        .map(|component| {
+            (
+                crate_identifier(component),
+                get_crate_settings_for_component(component, compilation_unit, metadata, options),
+            )
+        })
+        .collect();
+
+    AllCratesConfig {
+        global: global_crate_settings,
+        override_map,
+    }
+}
+
+fn get_global_crate_settings(
+    metadata: &Metadata,
+    compilation_unit: &CompilationUnitMetadata,
+    package: &PackageMetadata,
+    options: &EjectOptions,
+) -> CrateSettings {
+    let edition = options.edition.unwrap_or_else(|| {
+        if let Ok(None) = explicit_edition(&Some(package), package.name.as_str()) {
+            if fallback_edition(metadata, package).is_some() {
+                warn!(
+                    "package {} declares no edition, using the one of its workspace",
+                    package.name
+                );
+            }
+        }
+        main_package_edition(metadata, package)
+    });
+    let version = options
+        .package_version
+        .clone()
+        .unwrap_or_else(|| package.version.clone());
+    let cfg_set = get_cairo_cfg_set(&compilation_unit.cfg, package.name.as_str());
+    let cfg_set = merge_extra_cfg(cfg_set, &options.extra_cfg, "global crate settings");
+    let dependencies = collect_component_dependencies(
+        compilation_unit.components.iter(),
+        metadata,
+        compilation_unit,
+        options,
+    );
+    let experimental_features = get_experimental_features(Some(package), options);
+
+    CrateSettings {
+        name: None,
+        edition,
+        version: Some(version).filter(|_| !options.no_version),
+        cfg_set,
+        dependencies,
+        experimental_features,
+    }
+}
+
+/// Settings of a single component, as they appear in its `[config.override]` entry.
+pub fn get_crate_settings_for_component(
+    component: &CompilationUnitComponentMetadata,
+    compilation_unit: &CompilationUnitMetadata,
+    metadata: &Metadata,
+    options: &EjectOptions,
+) -> CrateSettings {
+    let package = metadata
+        .packages
+        .iter()
+        .find(|package| package.id == component.package);
+    if package.is_none() {
+        warn!(
+            "package {} of component {} is missing from metadata, \
+            its version is unknown and edition falls back to the default",
+            component.package, component.name
+        );
+    }
+    let edition = options
+        .crate_editions
+        .get(&component.name)
+        .copied()
+        .or(options
+            .edition
+            .filter(|_| component.package == compilation_unit.package))
+        .unwrap_or_else(|| match package {
+            Some(package) if package.id == compilation_unit.package => {
+                main_package_edition(metadata, package)
+            }
+            _ => get_edition(&package, component.name.as_str()),
+        });
+    let version = options
+        .package_version
+        .clone()
+        .filter(|_| component.package == compilation_unit.package)
+        .or_else(|| package.map(|p| p.version.clone()))
+        .filter(|_| !options.no_version);
+    let cfg_set = component
+        .cfg
+        .as_ref()
+        .and_then(|cfg| get_cairo_cfg_set(cfg, component.name.as_str()));
+    // Cairo only uses global settings for crates without an override, so items have to be
+    // repeated here to take effect.
+    let cfg_set = if component.package == compilation_unit.package {
+        merge_extra_cfg(
+            cfg_set,
+            &options.extra_cfg,
+            &format!("crate {}", component.name),
+        )
+    } else {
+        cfg_set
+    };
+    let dependencies = collect_component_dependencies(
+        component.dependencies.iter().flatten().filter_map(
+            |CompilationUnitComponentDependencyMetadata { id, .. }| {
+                let dependency = compilation_unit
+                    .components
+                    .iter()
+                    .find(|c| c.id.as_ref() == Some(id));
+                if dependency.is_none() {
+                    warn!(
+                        "dependency {id} of crate {} does not match any component, skipping it",
+                        component.name
+                    );
+                }
+                dependency
+            },
+        ),
+        metadata,
+        compilation_unit,
+        options,
+    );
+    let experimental_features = if options.crate_no_features.contains(&component.name) {
+        ExperimentalFeaturesConfig::default()
+    } else {
+        get_experimental_features(package, options)
+    };
+
+    CrateSettings {
+        name: Some(component.name.clone().into()),
+        edition,
+        version,
+        cfg_set,
+        dependencies,
+        experimental_features,
+    }
+}
+
+/// Key of the component in `[crate_roots]` and `[config.override]`.
+pub fn crate_identifier(component: &CompilationUnitComponentMetadata) -> CrateIdentifier {
+    component
+        .id
+        .clone()
+        .unwrap_or(component.name.clone().into())
+        .into()
+}
+
+/// Build a `dependencies` map of [`CrateSettings`] out of the given components.
+///
+/// Corelib is always skipped, as the compiler adds it implicitly, and dev-only dependencies are
+/// skipped if [`EjectOptions::exclude_dev_deps`] is set.
+fn collect_component_dependencies<'a>(
+    components: impl IntoIterator<Item = &'a CompilationUnitComponentMetadata>,
+    metadata: &Metadata,
+    compilation_unit: &CompilationUnitMetadata,
+    options: &EjectOptions,
+) -> BTreeMap<String, DependencySettings> {
+    components
+        .into_iter()
+        .filter(|c| c.name != CORELIB_CRATE_NAME)
+        .filter(|c| !options.exclude_dev_deps || !is_dev_only(metadata, compilation_unit, c))
+        .map(|c| {
+            (
+                c.name.clone(),
+                DependencySettings {
+                    discriminator: options
+                        .discriminator_strategy
+                        .apply(c.discriminator.as_deref()),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Whether `component` is part of the compilation unit only because of dev-dependencies,
+/// i.e. every package in the unit that depends on it does so with [`DepKind::Dev`].
+fn is_dev_only(
+    metadata: &Metadata,
+    compilation_unit: &CompilationUnitMetadata,
+    component: &CompilationUnitComponentMetadata,
+) -> bool {
+    let name = metadata
+        .get_package(&component.package)
+        .map_or(component.name.as_str(), |package| package.name.as_str());
+
+    let mut dependents = compilation_unit
+        .components
+        .iter()
+        .filter_map(|c| metadata.get_package(&c.package))
+        .flat_map(|package| &package.dependencies)
+        .filter(|dependency| dependency.name == name)
+        .peekable();
+
+    dependents.peek().is_some()
+        && dependents.all(|dependency| dependency.kind == Some(DepKind::Dev))
+}
+
+/// Get the [`Edition`] from [`PackageMetadata`], or assume the default edition.
+fn get_edition(package: &Option<&PackageMetadata>, crate_name: &str) -> Edition {
+    explicit_edition(package, crate_name)
+        .inspect_err(|e| warn!("{e:?}"))
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Parse the [`Edition`] declared in [`PackageMetadata`], if there is one.
+fn explicit_edition(
+    package: &Option<&PackageMetadata>,
+    crate_name: &str,
+) -> Result<Option<Edition>> {
+    package
+        .and_then(|p| p.edition.clone())
+        .map(|e| {
+            serde_json::from_value(e.into())
+                .with_context(|| format!("failed to parse edition of package: {crate_name}"))
+        })
+        .transpose()
+}
+
+/// Edition of the main package, falling back to [`fallback_edition`] if it declares none.
+fn main_package_edition(metadata: &Metadata, package: &PackageMetadata) -> Edition {
+    match explicit_edition(&Some(package), package.name.as_str()) {
+        Ok(Some(edition)) => edition,
+        Ok(None) => fallback_edition(metadata, package).unwrap_or_default(),
+        Err(e) => {
+            warn!("{e:?}");
+            Edition::default()
+        }
+    }
+}
+
+/// Edition for a main package that does not declare one, taken from the workspace.
+///
+/// The workspace-level `edition` is preferred, then the one all other members agree on.
+fn fallback_edition(metadata: &Metadata, package: &PackageMetadata) -> Option<Edition> {
+    if let Some(edition) = metadata.workspace.extra.get("edition") {
+        match serde_json::from_value::<Edition>(edition.clone()) {
+            Ok(edition) => return Some(edition),
+            Err(e) => warn!("failed to parse edition of the workspace: {e}"),
+        }
+    }
+
+    let member_editions = metadata
+        .packages
+        .iter()
+        .filter(|p| p.id != package.id && metadata.workspace.members.contains(&p.id))
+        .filter_map(|p| explicit_edition(&Some(p), p.name.as_str()).ok().flatten())
+        .collect::<Vec<_>>();
+    let (&edition, rest) = member_editions.split_first()?;
+    if rest.iter().any(|&e| e != edition) {
+        return None;
+    }
+    Some(edition)
+}
+
+/// Warn about or reject crates with roots outside of the workspace, except for the corelib.
+fn check_external_roots(
+    metadata: &Metadata,
+    compilation_unit: &CompilationUnitMetadata,
+    options: &EjectOptions,
+) -> Result<(), EjectError> {
+    let workspace_root = metadata.workspace.root.as_std_path();
+    let external = compilation_unit
+        .components
+        .iter()
+        .filter(|c| c.name != CORELIB_CRATE_NAME)
+        .filter(|c| {
+            !metadata
+                .workspace
+                .root
+                .join(c.source_root())
+                .as_std_path()
+                .starts_with(workspace_root)
+        })
+        .collect::<Vec<_>>();
+
+    if options.external_roots == ExternalRoots::Deny && !external.is_empty() {
+        return Err(EjectError::ExternalRoots {
+            workspace_root: workspace_root.to_path_buf(),
+            crates: external.iter().map(|c| c.name.clone()).collect(),
+        });
+    }
+
+    for c in external {
+        warn!(
+            "crate root of {} is outside of workspace {}: {}",
+            c.name,
+            workspace_root.display(),
+            c.source_root()
+        );
+    }
+    Ok(())
+}
+
+/// Fail if any crate would get the default edition because its package does not declare a
+/// parseable one, and for the main package, neither does its workspace.
+///
+/// Crates named in [`EjectOptions::crate_editions`] are exempt, and so is the main package if
+/// [`EjectOptions::edition`] is set.
+fn check_strict_editions(
+    metadata: &Metadata,
+    compilation_unit: &CompilationUnitMetadata,
+    options: &EjectOptions,
+) -> Result<(), EjectError> {
+    let defaulted = compilation_unit
+        .components
+        .iter()
+        .filter(|c| options.includes_component(c))
+        .filter(|c| options.edition.is_none() || c.package != compilation_unit.package)
+        .filter(|c| !options.crate_editions.contains_key(&c.name))
+        .filter(|c| {
+            let package = metadata.get_package(&c.package);
+            match explicit_edition(&package, c.name.as_str()) {
+                Ok(Some(_)) => false,
+                Ok(None) => !package.is_some_and(|package| {
+                    package.id == compilation_unit.package
+                        && fallback_edition(metadata, package).is_some()
+                }),
+                Err(_) => true,
+            }
+        })
+        .map(|c| c.name.clone())
+        .collect::<Vec<_>>();
+
+    if !defaulted.is_empty() {
+        return Err(EjectError::DefaultEdition { crates: defaulted });
+    }
+    Ok(())
+}
+
+/// Convert a slice of [`scarb_metadata::Cfg`]s, or of anything serializing like them, to a
+/// [`cairo_lang_filesystem::cfg::CfgSet`].
+///
+/// The conversion is done the same way as in Scarb (except no panicking):
+/// <https://github.com/software-mansion/scarb/blob/9fe97c8eb8620a1e2103e7f5251c5a9189e75716/scarb/src/ops/metadata.rs#L295-L302>
+///
+/// On failure a warning is logged and no cfg set is emitted at all.
+fn get_cairo_cfg_set(cfg_set: &[impl Serialize], crate_name: &str) -> Option<CfgSet> {
+    convert_cfg_set(cfg_set)
+        .with_context(|| {
+            format!(
+                "scarb metadata cfg did not convert identically to cairo one for crate: \
+                {crate_name}, leaving out all of its cfg items"
+            )
+        })
+        .inspect_err(|e| warn!("{e:?}"))
+        .ok()
+}
+
+fn convert_cfg_set(cfg_set: &[impl Serialize]) -> serde_json::Result<CfgSet> {
+    serde_json::to_value(cfg_set).and_then(serde_json::from_value)
+}
+
+/// Add `extra` cfg items on top of `cfg_set` of `scope`, warning when a key is already present.
+fn merge_extra_cfg(cfg_set: Option<CfgSet>, extra: &[Cfg], scope: &str) -> Option<CfgSet> {
+    if extra.is_empty() {
+        return cfg_set;
+    }
+
+    let mut cfg_set = cfg_set.unwrap_or_default();
+    for cfg in extra {
+        if cfg_set.iter().any(|existing| existing.key == cfg.key) {
+            warn!(
+                "cfg key `{}` is already set in {scope}, adding `{cfg}` anyway",
+                cfg.key
+            );
+        }
+        cfg_set.insert(cfg.clone());
+    }
+
+    Some(cfg_set)
+}
+
+/// Kebab-case spellings of experimental features, mapped to the snake-case names Cairo uses.
+///
+/// Cairo has never used these spellings itself. They are accepted because other multi-word keys
+/// of `Scarb.toml` are kebab-case, which makes them an easy mistake in `experimental-features`.
+const EXPERIMENTAL_FEATURE_ALIASES: &[(&str, &str)] = &[
+    ("negative-impls", "negative_impls"),
+    ("associated-item-constraints", "associated_item_constraints"),
+];
+
+/// Map a kebab-case experimental feature to its Cairo name, warning that `source` misspells it.
+fn canonical_feature_name<'a>(feature: &'a str, source: &str) -> &'a str {
+    match EXPERIMENTAL_FEATURE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == feature)
+    {
+        Some((alias, name)) => {
+            warn!("experimental feature `{alias}` in {source} is not a Cairo name, use `{name}`");
+            name
+        }
+        None => feature,
+    }
+}
+
+/// Get [`ExperimentalFeaturesConfig`] from [`PackageMetadata`] fields.
+///
+/// Features are matched against the serialized field names of [`ExperimentalFeaturesConfig`],
+/// so new features are picked up automatically once the Cairo dependency is bumped.
+///
+/// All features are disabled if [`EjectOptions::no_experimental_features`] is set.
+/// [`EjectOptions::experimental_features_override`] is applied last, for every crate.
+fn get_experimental_features(
+    package: Option<&PackageMetadata>,
+    options: &EjectOptions,
+) -> ExperimentalFeaturesConfig {
+    let mut fields = match serde_json::to_value(ExperimentalFeaturesConfig::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => unreachable!("experimental features config always serializes to an object"),
+    };
+
+    if let Some(package) = package.filter(|_| !options.no_experimental_features) {
+        for feature in &package.experimental_features {
+            let feature = canonical_feature_name(feature, &format!("package: {}", package.name));
+            match fields.get_mut(feature) {
+                Some(enabled) => *enabled = true.into(),
+                None => warn!(
+                    "unknown experimental feature `{feature}` in package: {}",
+                    package.name
+                ),
+            }
+        }
+    }
+
+    for (feature, &value) in &options.experimental_features_override {
+        let feature = canonical_feature_name(feature, "features override");
+        match fields.get_mut(feature) {
+            Some(enabled) => *enabled = value.into(),
+            None => warn!("unknown experimental feature `{feature}` in features override"),
+        }
+    }
+
+    serde_json::from_value(fields.into())
+        .expect("experimental features config must deserialize from its own fields")
+}
+
+#[cfg(test)]
+#[path = "../tests/common/fixture.rs"]
+mod fixture;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::fixture::Workspace;
+
+    fn project_config(workspace: &Workspace, options: &EjectOptions) -> ProjectConfigContent {
+        get_project_config(&workspace.parse(), &workspace.get("a"), options).unwrap()
+    }
+
+    fn settings<'a>(config: &'a ProjectConfigContent, id: &str) -> &'a CrateSettings {
+        config
+            .crates_config
+            .override_map
+            .get(&CrateIdentifier::from(id.to_owned()))
+            .unwrap()
+    }
+
+    #[test]
+    fn main_crate_falls_back_to_workspace_edition() {
+        let mut workspace = Workspace::chain();
+        workspace.packages[0]["edition"] = json!(null);
+        workspace.extra.insert("edition".into(), json!("2023_10"));
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        assert_eq!(config.crates_config.global.edition, Edition::V2023_10);
+        assert_eq!(settings(&config, "a").edition, Edition::V2023_10);
+        assert_eq!(settings(&config, "b").edition, Edition::V2024_07);
+
+        let options = EjectOptions {
+            strict_edition: true,
+            ..Default::default()
+        };
+        assert!(get_project_config(&workspace.parse(), &workspace.get("a"), &options).is_ok());
+
+        workspace.extra.clear();
+        workspace.packages[1]["edition"] = json!(null);
+        let err = get_project_config(&workspace.parse(), &workspace.get("a"), &options);
+        assert!(matches!(err, Err(EjectError::DefaultEdition { crates }) if crates == ["b"]));
+    }
+
+    /// Crate identifiers of `[config.override]` entries with the names of their dependencies.
+    fn dependency_graph(config: &ProjectConfigContent) -> Vec<(String, Vec<String>)> {
+        config
+            .crates_config
+            .override_map
+            .iter()
+            .map(|(id, settings)| {
+                let dependencies = settings.dependencies.keys().cloned().collect();
+                (SmolStr::from(id.clone()).to_string(), dependencies)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_crate_drops_dependencies_beyond_max_depth() {
+        let workspace = Workspace::chain();
+        let graph = |max_depth| {
+            let options = EjectOptions {
+                only_crate: Some("a".into()),
+                max_depth,
+                ..Default::default()
+            };
+            let config = project_config(&workspace, &options);
+            let roots = config
+                .crate_roots
+                .iter()
+                .map(|(id, _)| SmolStr::from(id.clone()).to_string())
+                .collect::<Vec<_>>();
+            let global = config
+                .crates_config
+                .global
+                .dependencies
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>();
+            (roots, global, dependency_graph(&config))
+        };
+        let owned = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            graph(Some(0)),
+            (owned(&["a"]), owned(&["a"]), vec![("a".into(), vec![])])
+        );
+        assert_eq!(
+            graph(Some(1)),
+            (
+                owned(&["a", "b"]),
+                owned(&["a", "b"]),
+                vec![("a".into(), owned(&["b"])), ("b".into(), vec![])]
+            )
+        );
+        assert_eq!(
+            graph(None),
+            (
+                owned(&["a", "b", "c"]),
+                owned(&["a", "b", "c"]),
+                vec![
+                    ("a".into(), owned(&["b"])),
+                    ("b".into(), owned(&["c"])),
+                    ("c".into(), vec![]),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn extra_cfg_reaches_crates_of_main_package() {
+        let options = EjectOptions {
+            extra_cfg: vec![Cfg::name("extra"), Cfg::kv("key", "value")],
+            ..Default::default()
+        };
+        let config = project_config(&Workspace::chain(), &options);
+
+        let has_extra = |cfg_set: &Option<CfgSet>| {
+            let cfg_set = cfg_set.as_ref().unwrap();
+            cfg_set.contains(&Cfg::name("extra")) && cfg_set.contains(&Cfg::kv("key", "value"))
+        };
+        assert!(has_extra(&config.crates_config.global.cfg_set));
+        assert!(has_extra(&settings(&config, "a").cfg_set));
+        assert!(settings(&config, "a")
+            .cfg_set
+            .as_ref()
+            .unwrap()
+            .contains(&Cfg::kv("target", "lib")));
+        assert!(!has_extra(&settings(&config, "b").cfg_set));
+    }
+
+    #[test]
+    fn kebab_case_experimental_features_are_accepted() {
+        let mut workspace = Workspace::chain();
+        workspace.packages[0]["experimental_features"] =
+            json!(["negative-impls", "associated-item-constraints"]);
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        let features = &settings(&config, "a").experimental_features;
+        assert!(features.negative_impls);
+        assert!(features.associated_item_constraints);
+        assert!(!features.coupons);
+    }
+
+    #[test]
+    fn known_experimental_features_are_enabled_everywhere() {
+        let mut workspace = Workspace::chain();
+        workspace.packages[0]["experimental_features"] =
+            json!(["negative_impls", "associated_item_constraints", "coupons"]);
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        let expected = ExperimentalFeaturesConfig {
+            negative_impls: true,
+            associated_item_constraints: true,
+            coupons: true,
+        };
+        assert_eq!(config.crates_config.global.experimental_features, expected);
+        assert_eq!(settings(&config, "a").experimental_features, expected);
+        assert_eq!(
+            settings(&config, "b").experimental_features,
+            ExperimentalFeaturesConfig::default()
+        );
+    }
+
+    #[test]
+    fn override_map_does_not_depend_on_component_order() {
+        let workspace = Workspace::chain();
+        let mut shuffled = Workspace::chain();
+        shuffled.units[0]["components_data"]
+            .as_array_mut()
+            .unwrap()
+            .reverse();
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        let shuffled = project_config(&shuffled, &EjectOptions::default());
+        assert_eq!(config.crates_config, shuffled.crates_config);
+        assert_eq!(
+            dependency_graph(&shuffled)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn package_without_compilation_units_suggests_building() {
+        let mut workspace = Workspace::chain();
+        workspace.units.clear();
+        let err = get_project_config(&workspace.parse(), &workspace.get("a"), &Default::default())
+            .unwrap_err();
+        assert!(matches!(err, EjectError::NoCompilationUnit { .. }));
+        assert!(err.to_string().contains("try running `scarb build` first"));
+
+        // Units of other packages do not count.
+        let err = get_project_config(
+            &Workspace::chain().parse(),
+            &workspace.get("b"),
+            &Default::default(),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, EjectError::NoCompilationUnit { package } if package.repr.starts_with("b "))
+        );
+    }
+
+    #[test]
+    fn relative_source_roots_are_resolved_against_workspace_root() {
+        let mut workspace = Workspace::chain();
+        workspace.units[0]["components_data"][1]["source_path"] = json!("b/src/lib.cairo");
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        let root = |id: &str| {
+            config
+                .crate_roots
+                .get(&CrateIdentifier::from(id.to_owned()))
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(root("a"), PathBuf::from("/ws/a/src"));
+        assert_eq!(root("b"), PathBuf::from("/ws/b/src"));
+    }
+
+    #[test]
+    fn no_experimental_features_disables_declared_ones() {
+        let mut workspace = Workspace::chain();
+        for package in &mut workspace.packages {
+            package["experimental_features"] =
+                json!(["negative_impls", "associated_item_constraints", "coupons"]);
+        }
+        let options = EjectOptions {
+            no_experimental_features: true,
+            ..Default::default()
+        };
+
+        let config = project_config(&workspace, &options);
+        assert_eq!(
+            config.crates_config.global.experimental_features,
+            ExperimentalFeaturesConfig::default()
+        );
+        for (_, settings) in config.crates_config.override_map.iter() {
+            assert_eq!(
+                settings.experimental_features,
+                ExperimentalFeaturesConfig::default()
+            );
+        }
+    }
+
+    #[test]
+    fn posix_paths_use_forward_slashes() {
+        let native = ["C:", "ws", "a", "src"].join(std::path::MAIN_SEPARATOR_STR);
+        assert_eq!(
+            to_posix_path(PathBuf::from(native)),
+            PathBuf::from("C:/ws/a/src")
+        );
+    }
+
+    #[test]
+    fn exclude_dev_deps_keeps_dependencies_also_needed_normally() {
+        let mut workspace = Workspace::default();
+        let a = workspace.package("a", &["b", "d", "e"]);
+        a["dependencies"][1]["kind"] = json!("dev");
+        a["dependencies"][2]["kind"] = json!("dev");
+        workspace.package("b", &["e"]);
+        workspace.package("d", &[]);
+        workspace.package("e", &[]);
+        workspace.unit(
+            "a",
+            "lib",
+            &[
+                ("a", &["b", "d", "e"]),
+                ("b", &["e"]),
+                ("d", &[]),
+                ("e", &[]),
+            ],
+        );
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        assert_eq!(
+            dependency_graph(&config)[0],
+            ("a".into(), vec!["b".into(), "d".into(), "e".into()])
+        );
+
+        let options = EjectOptions {
+            exclude_dev_deps: true,
+            ..Default::default()
+        };
+        let config = project_config(&workspace, &options);
+        assert_eq!(
+            dependency_graph(&config)[0],
+            ("a".into(), vec!["b".into(), "e".into()])
+        );
+        assert_eq!(dependency_graph(&config)[1], ("b".into(), vec!["e".into()]));
+        assert!(!config.crates_config.global.dependencies.contains_key("d"));
+    }
+
+    #[test]
+    fn collected_dependencies_skip_corelib_and_map_discriminators() {
+        let mut workspace = Workspace::chain();
+        workspace.units[0]["components_data"][1]["discriminator"] = json!("b-disc");
+        let metadata = workspace.parse();
+        let unit = &metadata.compilation_units[0];
+
+        let dependencies = |discriminator_strategy| {
+            let options = EjectOptions {
+                discriminator_strategy,
+                ..Default::default()
+            };
+            collect_component_dependencies(&unit.components, &metadata, unit, &options)
+                .into_iter()
+                .map(|(name, settings)| (name, settings.discriminator.map(String::from)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            dependencies(DiscriminatorStrategy::Keep),
+            [
+                ("a".into(), None),
+                ("b".into(), Some("b-disc".into())),
+                ("c".into(), None),
+            ]
+        );
+        assert_eq!(
+            dependencies(DiscriminatorStrategy::Drop),
+            [("a".into(), None), ("b".into(), None), ("c".into(), None)]
+        );
+    }
+
+    #[test]
+    fn units_with_the_same_target_name_are_picked_by_id() {
+        let mut workspace = Workspace::default();
+        workspace.package("a", &[]);
+        workspace.unit("a", "lib", &[("a", &[])])["id"] = json!("a-lib-2");
+        workspace.unit("a", "lib", &[("a", &[])])["id"] = json!("a-lib-1");
+
+        let selected = |workspace: &Workspace| {
+            let metadata = workspace.parse();
+            select_compilation_unit(&metadata, &workspace.get("a"), &Default::default())
+                .unwrap()
+                .id
+                .repr
+                .clone()
+        };
+        assert_eq!(selected(&workspace), "a-lib-1");
+        workspace.units.reverse();
+        assert_eq!(selected(&workspace), "a-lib-1");
+    }
+
+    #[test]
+    fn roots_only_leaves_crate_settings_at_defaults() {
+        let options = EjectOptions {
+            roots_only: true,
+            ..Default::default()
+        };
+        let config = project_config(&Workspace::chain(), &options);
+        assert_eq!(config.crate_roots.len(), 3);
+        assert_eq!(config.crates_config, AllCratesConfig::default());
+    }
+
+    #[test]
+    fn excluded_crate_vanishes_everywhere() {
+        let options = EjectOptions {
+            exclude: vec!["c".into()],
+            ..Default::default()
+        };
+        let config = project_config(&Workspace::chain(), &options);
+        let c = CrateIdentifier::from("c".to_owned());
+        assert!(config.crate_roots.get(&c).is_none());
+        assert!(config.crates_config.override_map.get(&c).is_none());
+        assert!(!config.crates_config.global.dependencies.contains_key("c"));
+        assert_eq!(
+            dependency_graph(&config),
+            [("a".into(), vec!["b".into()]), ("b".into(), vec![])]
+        );
+    }
+
+    #[test]
+    fn strict_edition_rejects_missing_and_unparseable_editions() {
+        let mut workspace = Workspace::chain();
+        workspace.packages[1]["edition"] = json!(null);
+        workspace.packages[2]["edition"] = json!("2099_99");
+        let options = EjectOptions {
+            strict_edition: true,
+            ..Default::default()
+        };
+
+        let err = get_project_config(&workspace.parse(), &workspace.get("a"), &options);
+        assert!(matches!(err, Err(EjectError::DefaultEdition { crates }) if crates == ["b", "c"]));
+
+        // Crates with an edition override do not fall back to the default.
+        let overridden = EjectOptions {
+            crate_editions: [("b".to_owned(), Edition::V2024_07)].into(),
+            ..options
+        };
+        let err = get_project_config(&workspace.parse(), &workspace.get("a"), &overridden);
+        assert!(matches!(err, Err(EjectError::DefaultEdition { crates }) if crates == ["c"]));
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        assert_eq!(settings(&config, "b").edition, Edition::default());
+        assert_eq!(settings(&config, "c").edition, Edition::default());
+    }
+
+    #[test]
+    fn prune_unreferenced_drops_unreachable_crates() {
+        let mut workspace = Workspace::default();
+        workspace.package("a", &["b"]);
+        workspace.package("b", &[]);
+        workspace.package("d", &[]);
+        workspace.unit("a", "lib", &[("a", &["b"]), ("b", &[]), ("d", &[])]);
+        let options = EjectOptions {
+            prune_unreferenced: true,
+            ..Default::default()
+        };
+
+        let config = project_config(&workspace, &EjectOptions::default());
+        assert_eq!(config.crate_roots.len(), 3);
+
+        let config = project_config(&workspace, &options);
+        let d = CrateIdentifier::from("d".to_owned());
+        assert!(config.crate_roots.get(&d).is_none());
+        assert!(config.crates_config.override_map.get(&d).is_none());
+        assert!(!config.crates_config.global.dependencies.contains_key("d"));
+        assert_eq!(config.crate_roots.len(), 2);
+    }
+
+    #[test]
+    fn package_version_overrides_main_package_only() {
+        let version = Version::new(9, 9, 9);
+        let options = EjectOptions {
+            package_version: Some(version.clone()),
+            ..Default::default()
+        };
+        let config = project_config(&Workspace::chain(), &options);
+        assert_eq!(config.crates_config.global.version, Some(version.clone()));
+        assert_eq!(settings(&config, "a").version, Some(version));
+        assert_eq!(settings(&config, "b").version, Some(Version::new(0, 1, 0)));
+    }
+
+    #[test]
+    fn minimize_keeps_overrides_that_rename_or_differ() {
+        let global = CrateSettings {
+            version: Some(Version::new(0, 1, 0)),
+            ..Default::default()
+        };
+        let entry = |name: &str, edition| CrateSettings {
+            name: Some(name.into()),
+            edition,
+            ..global.clone()
+        };
+        let mut crates_config = AllCratesConfig {
+            global: global.clone(),
+            override_map: [
+                ("same", entry("same", Edition::default())),
+                ("renamed", entry("other", Edition::default())),
+                ("newer", entry("newer", Edition::V2024_07)),
+            ]
+            .into_iter()
+            .map(|(id, settings)| (CrateIdentifier::from(id.to_owned()), settings))
+            .collect(),
+        };
+
+        remove_redundant_overrides(&mut crates_config);
+        let mut names = crates_config
+            .override_map
+            .iter()
+            .map(|(id, settings)| (SmolStr::from(id.clone()), settings.name.clone()))
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                ("newer".into(), Some("newer".into())),
+                ("renamed".into(), Some("other".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_name_what_is_missing() {
+        let workspace = Workspace::chain();
+        let error = |options: EjectOptions| {
+            get_project_config(&workspace.parse(), &workspace.get("a"), &options).unwrap_err()
+        };
+
+        let err = error(EjectOptions {
+            target: Some("nope".into()),
+            ..Default::default()
+        });
+        assert!(matches!(
+            err,
+            EjectError::TargetNotFound { target: Some(target), kind: None, available, .. }
+                if target == "nope" && available == [("lib".to_owned(), "lib".to_owned())]
+        ));
+
+        let err = error(EjectOptions {
+            only_crate: Some("nope".into()),
+            ..Default::default()
+        });
+        assert!(matches!(err, EjectError::CrateNotFound { name, .. } if name == "nope"));
+
+        let err = error(EjectOptions {
+            corelib_path: Some("/no/such/corelib".into()),
+            ..Default::default()
+        });
+        assert!(
+            matches!(err, EjectError::CorelibRootNotFound { root, .. } if root == Path::new("/no/such/corelib"))
+        );
+    }
+
+    #[test]
+    fn member_roots_only_drops_roots_of_other_packages() {
+        let workspace = Workspace::chain();
+        let mut metadata = workspace.parse();
+        metadata
+            .workspace
+            .members
+            .retain(|id| !id.repr.starts_with("c "));
+        let options = EjectOptions {
+            member_roots_only: true,
+            ..Default::default()
+        };
+
+        let config = get_project_config(&metadata, &workspace.get("a"), &options).unwrap();
+        let roots = config
+            .crate_roots
+            .iter()
+            .map(|(id, _)| SmolStr::from(id.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(roots, ["a", "b"]);
+        assert_eq!(settings(&config, "c").name, Some("c".into()));
+    }
+
+    #[test]
+    fn contracts_are_preferred_unless_a_target_is_given() {
+        let selected = |workspace: &Workspace, target: Option<&str>| {
+            let options = EjectOptions {
+                target: target.map(String::from),
+                ..Default::default()
+            };
+            let metadata = workspace.parse();
+            select_compilation_unit(&metadata, &workspace.get("a"), &options)
+                .map(|unit| unit.id.repr.clone())
+        };
+        let mut workspace = Workspace::default();
+        workspace.package("a", &[]);
+        assert!(matches!(
+            selected(&workspace, None),
+            Err(EjectError::NoCompilationUnit { .. })
+        ));
+
+        workspace.unit("a", "test", &[("a", &[])]);
+        workspace.unit("a", "lib", &[("a", &[])]);
+        assert_eq!(selected(&workspace, None).unwrap(), "a-lib");
+
+        workspace.unit("a", "starknet-contract", &[("a", &[])]);
+        assert_eq!(selected(&workspace, None).unwrap(), "a-starknet-contract");
+        assert_eq!(selected(&workspace, Some("lib")).unwrap(), "a-lib");
+        assert_eq!(selected(&workspace, Some("test")).unwrap(), "a-test");
+    }
+
+    #[test]
+    fn target_kind_filters_units_on_top_of_target_name() {
+        let mut workspace = Workspace::default();
+        workspace.package("a", &[]);
+        for (kind, name) in [
+            ("test", "a_unittest"),
+            ("lib", "a"),
+            ("starknet-contract", "a"),
+        ] {
+            workspace.unit("a", kind, &[("a", &[])])["target"]["name"] = json!(name);
+        }
+        let selected = |target: Option<&str>, kind: &str| {
+            let options = EjectOptions {
+                target: target.map(String::from),
+                target_kind: Some(kind.into()),
+                ..Default::default()
+            };
+            let metadata = workspace.parse();
+            select_compilation_unit(&metadata, &workspace.get("a"), &options)
+                .map(|unit| unit.id.repr.clone())
+        };
+
+        assert_eq!(selected(None, "test").unwrap(), "a-test");
+        assert_eq!(selected(None, "lib").unwrap(), "a-lib");
+        assert_eq!(selected(Some("a"), "lib").unwrap(), "a-lib");
+        assert!(matches!(
+            selected(Some("a"), "test"),
+            Err(EjectError::TargetNotFound {
+                target: Some(_),
+                kind: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn cfg_that_does_not_convert_is_left_out() {
+        let cfg_set = [json!("name"), json!(["key", "value"])];
+        assert_eq!(
+            get_cairo_cfg_set(&cfg_set, "a"),
+            Some(CfgSet::from_iter([
+                Cfg::name("name"),
+                Cfg::kv("key", "value")
+            ]))
+        );
+
+        let cfg_set = [json!("name"), json!(["key", "value", "extra"])];
+        assert!(convert_cfg_set(&cfg_set).is_err());
+        assert_eq!(get_cairo_cfg_set(&cfg_set, "a"), None);
+    }
+
+    #[test]
+    fn discriminator_strategy_applies_to_crate_dependencies() {
+        let mut workspace = Workspace::chain();
+        workspace.units[0]["components_data"][1]["discriminator"] = json!("b-disc");
+        let discriminator = |discriminator_strategy| {
+            let options = EjectOptions {
+                discriminator_strategy,
+                ..Default::default()
+            };
+            let config = project_config(&workspace, &options);
+            settings(&config, "a").dependencies["b"]
+                .discriminator
+                .clone()
+                .map(String::from)
+        };
+
+        assert_eq!(
+            discriminator(DiscriminatorStrategy::Keep).as_deref(),
+            Some("b-disc")
+        );
+        assert_eq!(discriminator(DiscriminatorStrategy::Drop), None);
+        // Pinned, so that configs do not change with Rust or platform.
+        assert_eq!(
+            discriminator(DiscriminatorStrategy::Hash).as_deref(),
+            Some("091b8a4d")
+        );
+    }
+
+    #[test]
+    fn two_crate_cycle_is_walked_once_and_reported() {
+        let mut workspace = Workspace::default();
+        workspace.package("a", &["b"]);
+        workspace.package("b", &["a"]);
+        workspace.unit("a", "lib", &[("a", &["b"]), ("b", &["a"])]);
+        let metadata = workspace.parse();
+        assert_eq!(
+            dependency_cycles(&metadata.compilation_units[0]),
+            [["a", "b", "a"]]
+        );
+
+        let options = EjectOptions {
+            only_crate: Some("a".into()),
+            detect_cycles: true,
+            ..Default::default()
+        };
+        let config = project_config(&workspace, &options);
+        assert_eq!(
+            dependency_graph(&config),
+            [
+                ("a".into(), vec!["b".into()]),
+                ("b".into(), vec!["a".into()])
+            ]
+        );
+
+        let options = EjectOptions {
+            strict: true,
+            ..options
+        };
+        let err = get_project_config(&metadata, &workspace.get("a"), &options).unwrap_err();
+        assert!(matches!(err, EjectError::DependencyCycle { cycle } if cycle == ["a", "b", "a"]));
+    }
+
+    #[test]
+    fn validate_cfg_accepts_keyed_and_bare_items_but_not_lost_ones() {
+        let mut workspace = Workspace::chain();
+        let cfg = json!([["target", "lib"], "bare"]);
+        workspace.units[0]["cfg"] = cfg.clone();
+        workspace.units[0]["components_data"][0]["cfg"] = cfg;
+        let options = EjectOptions {
+            validate_cfg: true,
+            ..Default::default()
+        };
+
+        let config = project_config(&workspace, &options);
+        let expected = CfgSet::from_iter([Cfg::kv("target", "lib"), Cfg::name("bare")]);
+        assert_eq!(config.crates_config.global.cfg_set, Some(expected.clone()));
+        assert_eq!(settings(&config, "a").cfg_set, Some(expected));
+
+        // Duplicates collapse in a set.
+        workspace.units[0]["components_data"][1]["cfg"] = json!(["bare", "bare"]);
+        let err =
+            get_project_config(&workspace.parse(), &workspace.get("a"), &options).unwrap_err();
+        assert!(matches!(
+            err,
+            EjectError::CfgConversion { crate_name, message }
+                if crate_name == "b" && message == "1 of 2 cfg items survived"
+        ));
+    }
+
+    #[test]
+    fn corelib_path_follows_root_kind() {
+        // Roots must exist for `validate`.
+        let dir = std::env::temp_dir().join(format!("scarb-eject-corelib-{}", std::process::id()));
+        for name in ["a", "b", "c"] {
+            fs::create_dir_all(dir.join(name).join("src")).unwrap();
+            fs::write(dir.join(name).join("src/lib.cairo"), "").unwrap();
+        }
+        let corelib = dir.join("corelib");
+        fs::create_dir_all(&corelib).unwrap();
+        let workspace = Workspace::chain_at(&dir);
+        let corelib_root = |root_kind| {
+            let options = EjectOptions {
+                corelib_path: Some(corelib.clone()),
+                root_kind,
+                validate: true,
+                ..Default::default()
+            };
+            get_project_config(&workspace.parse(), &workspace.get("a"), &options).map(|config| {
+                config
+                    .crate_roots
+                    .get(&CrateIdentifier::from("core".to_owned()))
+                    .cloned()
+            })
+        };
+
+        assert_eq!(corelib_root(RootKind::Dir).unwrap(), Some(corelib.clone()));
+        let err = corelib_root(RootKind::File).unwrap_err();
+        assert!(matches!(
+            err,
+            EjectError::CorelibRootNotFound { root, root_kind: RootKind::File }
+                if root == corelib.join("lib.cairo")
+        ));
+
+        fs::write(corelib.join("lib.cairo"), "").unwrap();
+        assert_eq!(
+            corelib_root(RootKind::File).unwrap(),
+            Some(corelib.join("lib.cairo"))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
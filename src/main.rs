@@ -1,259 +1,1946 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::process;
 
-use anyhow::{anyhow, Context, Result};
-use cairo_lang_filesystem::cfg::CfgSet;
-use cairo_lang_filesystem::db::{
-    CrateIdentifier, CrateSettings, DependencySettings, Edition, ExperimentalFeaturesConfig,
-    CORELIB_CRATE_NAME,
-};
-use cairo_lang_project::{AllCratesConfig, ProjectConfigContent};
-use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
-use clap::Parser;
-use scarb_metadata::{
-    CompilationUnitComponentDependencyMetadata, CompilationUnitComponentMetadata,
-    CompilationUnitMetadata, Metadata, PackageMetadata,
+use anyhow::{anyhow, bail, Context, Result};
+use cairo_lang_filesystem::cfg::Cfg;
+use cairo_lang_filesystem::db::{CrateIdentifier, Edition};
+use clap::builder::{OsStringValueParser, TypedValueParser};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use scarb_eject::{
+    cairo_version, crate_identifier, get_crate_settings_for_component, get_crates_config,
+    get_project_config, merge_project_config, select_compilation_unit, selection_priority,
+    stable_hash, AllCratesConfig, DiscriminatorStrategy, EjectError, EjectOptions, ExternalRoots,
+    ProjectConfigContent, RootKind,
 };
+use scarb_metadata::{CompilationUnitMetadata, Metadata, PackageMetadata};
 use scarb_ui::args::PackagesFilter;
-use tracing::warn;
+use semver::Version;
+use similar::TextDiff;
+use smol_str::SmolStr;
+use tracing::{debug, info, warn, Level};
+use tracing_subscriber::EnvFilter;
+
+mod canonical;
+mod defaults;
+mod schema;
 
 #[derive(Parser, Clone, Debug)]
-#[command(about, author, version)]
+#[command(about, author, version, args_override_self = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to `cairo_project.toml` file to overwrite.
     /// Defaults to next to `Scarb.toml` for this workspace.
     /// Use `-` to write to standard output.
-    #[arg(short, long, value_name = "PATH")]
-    output: Option<PathBuf>,
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        value_parser = OsStringValueParser::new().map(OutputTarget::from)
+    )]
+    output: Option<OutputTarget>,
+
+    /// Write a separate config for every crate into this directory, named after the crate.
+    /// Fails if two crates share a name.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "all"])]
+    output_dir: Option<PathBuf>,
+
+    /// Path to `Scarb.toml` of the project to eject.
+    /// Defaults to searching the current directory and its ancestors.
+    #[arg(long, value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+
+    /// Read `scarb metadata --format-version 1` JSON output from this file instead of running
+    /// Scarb. Use `-` to read from standard input.
+    #[arg(long, value_name = "PATH", conflicts_with = "manifest_path")]
+    metadata_file: Option<PathBuf>,
+
+    /// Reuse `scarb metadata` output stored in this file while workspace manifests and the
+    /// lockfile are unchanged, refreshing it otherwise.
+    #[arg(long, value_name = "PATH", conflicts_with = "metadata_file")]
+    metadata_cache: Option<PathBuf>,
+
+    /// Run `scarb metadata` without accessing the network.
+    #[arg(long)]
+    offline: bool,
+
+    /// Like `--offline`, and additionally fail if `Scarb.lock` would be updated.
+    /// This is checked after running Scarb, restoring the original lockfile if it changed.
+    #[arg(long)]
+    frozen: bool,
+
+    /// Scarb profile to generate the config for. Defaults to Scarb's default profile.
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Format of the generated project config.
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Write `cairo_project.dev.toml` and `cairo_project.release.toml` into the workspace root,
+    /// generated for the `dev` and `release` profiles respectively.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "profile", "output", "output_dir", "all", "per_package", "packages_glob",
+            "metadata_file", "fragment",
+        ]
+    )]
+    both_profiles: bool,
+
+    /// Pipe the generated config through this command, e.g. a TOML formatter, and use its
+    /// output instead.
+    #[arg(long, value_name = "CMD")]
+    format_with: Option<String>,
+
+    /// Line endings of the written file.
+    #[arg(long, value_enum, default_value_t)]
+    line_ending: LineEnding,
+
+    /// What to do when the output file already exists: overwrite it, fail, or only overwrite it
+    /// if the generated content differs.
+    #[arg(long, value_enum, default_value_t)]
+    overwrite_policy: OverwritePolicy,
+
+    /// Encoding of the written file.
+    #[arg(long, value_enum, default_value_t)]
+    output_encoding: OutputEncoding,
+
+    /// Stage the written file with `git add`.
+    #[arg(long, conflicts_with_all = ["check", "diff", "dry_run"])]
+    git_add: bool,
+
+    /// Leave out empty tables, like `dependencies` of crates without any. TOML only.
+    #[arg(long)]
+    strip_empty_sections: bool,
+
+    /// Annotate each crate root with the id of its package, including its version. TOML only.
+    #[arg(long)]
+    annotate: bool,
+
+    /// Stamp the header with a hash of the workspace `Scarb.lock`, which `--check` verifies.
+    #[arg(long)]
+    with_lockfile_hash: bool,
+
+    /// Do not write anything, instead fail if the output file is not up to date.
+    #[arg(long)]
+    check: bool,
+
+    /// Do not write anything, instead print a unified diff against the output file.
+    #[arg(long, conflicts_with = "check")]
+    diff: bool,
+
+    /// Keep the corelib crate in the generated config.
+    #[arg(long)]
+    include_corelib: bool,
+
+    /// Warn if the corelib version differs from the Cairo version scarb-eject was built with.
+    #[arg(long)]
+    check_corelib: bool,
+
+    /// Leave dependencies only needed as dev-dependencies out of crate settings.
+    #[arg(long)]
+    exclude_dev_deps: bool,
+
+    /// Only emit this crate and crates it transitively depends on.
+    #[arg(long, value_name = "NAME")]
+    only_crate: Option<String>,
+
+    /// With `--only-crate`, only follow dependencies this many hops deep. `0` keeps just the
+    /// crate itself.
+    #[arg(long, value_name = "N", requires = "only_crate")]
+    max_depth: Option<usize>,
+
+    /// Eject the compilation unit of the given target instead of picking one automatically.
+    #[arg(long, value_name = "NAME")]
+    target: Option<String>,
+
+    /// Only consider compilation units of targets of this kind, e.g. `lib`,
+    /// `starknet-contract` or `test`. Combines with `--target`.
+    #[arg(long, value_name = "KIND")]
+    target_kind: Option<String>,
+
+    /// Eject the package with this `Scarb.toml`, instead of selecting it by name.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["package", "workspace", "all"])]
+    main_manifest: Option<PathBuf>,
+
+    /// Print the compilation unit that would be ejected as JSON, without generating a config.
+    #[arg(long, conflicts_with_all = ["all", "per_package", "merge_targets"])]
+    print_selected_unit: bool,
+
+    /// Print resolved settings of the crate with this name, without generating a config.
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["all", "per_package", "packages_glob", "merge_targets"]
+    )]
+    inspect_crate: Option<String>,
+
+    /// Explain on standard error which compilation units were considered and why one was chosen.
+    #[arg(long, conflicts_with_all = ["all", "per_package", "packages_glob", "merge_targets"])]
+    explain_selection: bool,
+
+    /// Print the path the config would be written to, without generating it.
+    #[arg(long, conflicts_with_all = ["all", "per_package", "packages_glob", "output_dir"])]
+    print_path: bool,
+
+    /// Print every component of the selected compilation unit with its package, root,
+    /// discriminator, edition and number of dependencies, as a table or as JSON with
+    /// `--format json`, without generating a config.
+    #[arg(long, conflicts_with_all = ["all", "per_package", "merge_targets"])]
+    components_report: bool,
+
+    /// Eject several targets of the package into a single config, separated by commas.
+    /// Settings of the later targets win where they conflict.
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        conflicts_with_all = ["target", "output_dir", "all", "per_package"]
+    )]
+    merge_targets: Vec<String>,
+
+    /// Eject every workspace member, writing `cairo_project.toml` next to each package.
+    /// With `--output -`, configs are printed one after another, or as a single object keyed by
+    /// package name with `--format json`.
+    #[arg(long, conflicts_with_all = ["package", "workspace"])]
+    all: bool,
+
+    /// Write `cairo_project.toml` next to the manifest of each selected package,
+    /// instead of a single file at the workspace root.
+    #[arg(long, conflicts_with_all = ["all", "output_dir"])]
+    per_package: bool,
+
+    /// Like `--per-package`, for workspace members whose name, or directory or manifest path
+    /// relative to the workspace root, matches the glob. `*` matches within a path segment.
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        conflicts_with_all = ["all", "per_package", "output_dir", "package", "workspace"]
+    )]
+    packages_glob: Option<String>,
+
+    /// Generate everything, but only report what would be written instead of touching disk.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Copy an existing output file to `<PATH>.bak` before overwriting it.
+    #[arg(long)]
+    backup: bool,
+
+    /// Fail if any generated crate root does not exist on disk.
+    #[arg(long)]
+    validate: bool,
+
+    /// Fail if any cfg item from metadata is lost when converting it to a Cairo cfg set.
+    #[arg(long)]
+    validate_cfg: bool,
+
+    /// Warn about dependency cycles between crates, or fail under `--strict`.
+    #[arg(long)]
+    detect_cycles: bool,
+
+    /// Disable all experimental features of the crate with the given name. May be repeated.
+    #[arg(long, value_name = "NAME")]
+    crate_no_features: Vec<String>,
+
+    /// Override the edition of the crate with the given name. May be repeated.
+    #[arg(long, value_name = "NAME=EDITION", value_parser = parse_crate_edition)]
+    crate_edition: Vec<(String, Edition)>,
+
+    /// Override the version of the main package in the emitted crate settings.
+    #[arg(long, value_name = "SEMVER")]
+    package_version: Option<Version>,
+
+    /// Leave crate versions out of the emitted crate settings.
+    #[arg(long, conflicts_with = "package_version")]
+    no_version: bool,
+
+    /// Warn about every crate root outside of the workspace root, except for the corelib.
+    #[arg(long)]
+    warn_external_roots: bool,
+
+    /// Fail if any crate root, except for the corelib, is outside of the workspace root.
+    #[arg(long)]
+    deny_external_roots: bool,
+
+    /// Fail if the same crate name maps to two different crate roots.
+    #[arg(long)]
+    deny_root_conflicts: bool,
+
+    /// Fail when metadata is incomplete, e.g. a component's package is missing, instead of
+    /// falling back to defaults.
+    #[arg(long)]
+    strict: bool,
+
+    /// Fail if any crate does not declare a valid edition and would get the default one.
+    #[arg(long)]
+    strict_edition: bool,
+
+    /// Override the Cairo edition of the main package.
+    #[arg(long, value_name = "EDITION", value_parser = parse_edition)]
+    edition: Option<Edition>,
+
+    /// Whether crate roots should point at source directories or crate root files.
+    #[arg(long, value_enum, default_value_t)]
+    root_kind: RootKindArg,
+
+    /// How discriminators of dependencies are emitted: as they are, left out or hashed.
+    #[arg(long, value_enum, default_value_t)]
+    discriminator_strategy: DiscriminatorStrategyArg,
+
+    /// Resolve symlinks in crate roots to real paths.
+    #[arg(long)]
+    canonicalize: bool,
+
+    /// Use `/` as the path separator in crate roots, so configs are identical across platforms.
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set)]
+    posix_paths: bool,
+
+    /// Emit crate roots relative to the directory containing the output file.
+    /// Same as `--relative-to output`.
+    #[arg(long)]
+    relative: bool,
+
+    /// Emit crate roots relative to the workspace root (`workspace`), the directory containing
+    /// the output file (`output`) or the given directory. The corelib and roots outside of both
+    /// the base and the workspace stay absolute.
+    #[arg(
+        long,
+        value_name = "BASE",
+        value_parser = parse_relative_base,
+        conflicts_with = "relative"
+    )]
+    relative_to: Option<RelativeBase>,
+
+    /// Add a cfg item to the global crate settings and to crates of the main package, on top of
+    /// the ones from metadata. May be repeated.
+    #[arg(long = "cfg", value_name = "KEY[=VALUE]", value_parser = parse_cfg)]
+    cfg: Vec<Cfg>,
+
+    /// Add cfg items from this environment variable, holding a comma-separated list of
+    /// `KEY[=VALUE]` items, the same way as with `--cfg`. May be repeated.
+    #[arg(long, value_name = "VAR")]
+    append_cfg_from_env: Vec<String>,
+
+    /// Disable all experimental features, regardless of what packages declare.
+    #[arg(long)]
+    no_experimental_features: bool,
+
+    /// Use the corelib in this directory instead of the one resolved by Scarb.
+    /// With `--root-kind file`, the crate root is `lib.cairo` in it.
+    #[arg(long, value_name = "DIR")]
+    corelib_path: Option<PathBuf>,
+
+    /// Leave out crates that the main package does not transitively depend on.
+    #[arg(long)]
+    prune_unreferenced: bool,
+
+    /// Leave out the crate with this name, along with every dependency on it. May be repeated.
+    #[arg(long, value_name = "NAME")]
+    exclude: Vec<String>,
+
+    /// Only emit crate roots of workspace members, keeping settings of all crates.
+    /// The compiler will not find other crates unless their roots are provided another way.
+    #[arg(long)]
+    include_only_target_package_roots: bool,
+
+    /// Leave out per-crate settings identical to the global ones.
+    /// Entries are only removed as a whole and only if the crate name would not change.
+    #[arg(long)]
+    minimize: bool,
+
+    /// Only emit `[crate_roots]`, leaving out all crate settings.
+    #[arg(long)]
+    roots_only: bool,
+
+    /// TOML file mapping experimental feature names to `true` or `false`,
+    /// forcing them on or off in every crate.
+    #[arg(long, value_name = "PATH")]
+    features_override: Option<PathBuf>,
+
+    /// Do not put a comment with generation details at the top of the TOML output.
+    #[arg(long)]
+    no_header: bool,
+
+    /// Print just the generated tables to standard output, without a header or surrounding
+    /// blank lines, for embedding into a larger file. Implies `--output -`.
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "output_dir", "all", "per_package", "check", "diff"]
+    )]
+    fragment: bool,
+
+    /// Keep comment lines from the top of the existing output file, before its first table.
+    /// Comments anywhere else in the file are not preserved.
+    #[arg(long)]
+    preserve_comments: bool,
+
+    /// Verify that the generated config parses back into an identical project config.
+    #[arg(long)]
+    self_check: bool,
+
+    /// Format of error messages.
+    #[arg(long, value_enum, default_value_t)]
+    message_format: MessageFormat,
+
+    /// Log more details to standard error, including the resolved discriminator of every
+    /// component. May be repeated.
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Maximum level of log messages printed to standard error.
+    /// Takes precedence over `--verbose` and the `RUST_LOG` environment variable.
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<Level>,
 
     #[command(flatten)]
     packages_filter: PackagesFilter,
 }
 
+/// Directory that crate roots are made relative to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RelativeBase {
+    Workspace,
+    Output,
+    Dir(PathBuf),
+}
+
+fn parse_relative_base(base: &str) -> Result<RelativeBase> {
+    Ok(match base {
+        "workspace" => RelativeBase::Workspace,
+        "output" => RelativeBase::Output,
+        dir => RelativeBase::Dir(PathBuf::from(dir)),
+    })
+}
+
+/// Where to write the generated config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum OutputTarget {
+    /// Standard output, passed as exactly `-`.
+    Stdout,
+    /// A file path, including ones like `./-` that only look like the sentinel.
+    File(PathBuf),
+}
+
+impl From<OsString> for OutputTarget {
+    fn from(value: OsString) -> Self {
+        if value == "-" {
+            Self::Stdout
+        } else {
+            Self::File(value.into())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// List compilation units available for ejection in the selected package.
+    List {
+        /// Format of the listing.
+        #[arg(long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
+    /// Print a JSON Schema of `cairo_project.toml`, for editor completion.
+    #[command(hide = true)]
+    Schema,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ListFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum MessageFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum DiscriminatorStrategyArg {
+    #[default]
+    Keep,
+    Drop,
+    Hash,
+}
+
+impl From<DiscriminatorStrategyArg> for DiscriminatorStrategy {
+    fn from(strategy: DiscriminatorStrategyArg) -> Self {
+        match strategy {
+            DiscriminatorStrategyArg::Keep => DiscriminatorStrategy::Keep,
+            DiscriminatorStrategyArg::Drop => DiscriminatorStrategy::Drop,
+            DiscriminatorStrategyArg::Hash => DiscriminatorStrategy::Hash,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum RootKindArg {
+    #[default]
+    Dir,
+    File,
+}
+
+impl From<RootKindArg> for RootKind {
+    fn from(kind: RootKindArg) -> Self {
+        match kind {
+            RootKindArg::Dir => RootKind::Dir,
+            RootKindArg::File => RootKind::File,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+impl OutputFormat {
+    fn default_file_name(self) -> &'static str {
+        match self {
+            OutputFormat::Toml => "cairo_project.toml",
+            OutputFormat::Json => "cairo_project.json",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Toml => "toml",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    /// CRLF on Windows, LF elsewhere.
+    Native,
+}
+
+impl LineEnding {
+    /// Convert LF line endings of `content` into this line ending.
+    fn apply(self, content: &str) -> String {
+        match self {
+            LineEnding::Crlf => content.replace('\n', "\r\n"),
+            LineEnding::Native if cfg!(windows) => content.replace('\n', "\r\n"),
+            LineEnding::Lf | LineEnding::Native => content.to_owned(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OverwritePolicy {
+    #[default]
+    Always,
+    Never,
+    /// Leave the file, including its modification time, alone if it is up to date.
+    IfChanged,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 prefixed with a byte order mark.
+    Utf8Bom,
+}
+
+/// All editions known to the Cairo version this tool is built against.
+const EDITIONS: [Edition; 4] = [
+    Edition::V2023_01,
+    Edition::V2023_10,
+    Edition::V2023_11,
+    Edition::V2024_07,
+];
+
+fn parse_edition(edition: &str) -> Result<Edition> {
+    serde_json::from_value(edition.into()).map_err(|_| {
+        let accepted = EDITIONS
+            .map(|edition| {
+                serde_json::to_string(&edition)
+                    .unwrap()
+                    .trim_matches('"')
+                    .to_owned()
+            })
+            .join(", ");
+        anyhow!("unknown edition `{edition}`, accepted values: {accepted}")
+    })
+}
+
+/// Cfg items from `--cfg`, followed by the ones from `--append-cfg-from-env` variables.
+fn extra_cfg(args: &Args) -> Result<Vec<Cfg>> {
+    let mut cfg = args.cfg.clone();
+    for var in &args.append_cfg_from_env {
+        let Some(items) = env::var_os(var) else {
+            warn!("environment variable {var} is not set, no cfg items added from it");
+            continue;
+        };
+        let items = items
+            .into_string()
+            .map_err(|_| anyhow!("environment variable {var} is not valid unicode"))?;
+        for item in items.split(',').filter(|item| !item.trim().is_empty()) {
+            cfg.push(
+                parse_cfg(item)
+                    .with_context(|| format!("invalid cfg item in environment variable {var}"))?,
+            );
+        }
+    }
+    Ok(cfg)
+}
+
+fn parse_crate_edition(crate_edition: &str) -> Result<(String, Edition)> {
+    let (name, edition) = crate_edition
+        .split_once('=')
+        .context("expected `NAME=EDITION`")?;
+    if name.trim().is_empty() {
+        bail!("crate name must not be empty");
+    }
+    Ok((name.trim().to_owned(), parse_edition(edition.trim())?))
+}
+
+fn parse_cfg(cfg: &str) -> Result<Cfg> {
+    let cfg = match cfg.split_once('=') {
+        Some((key, value)) => Cfg::kv(key.trim(), value.trim().trim_matches('"')),
+        None => Cfg::name(cfg.trim()),
+    };
+    if cfg.key.is_empty() {
+        bail!("cfg key must not be empty");
+    }
+    Ok(cfg)
+}
+
 fn main() -> Result<()> {
-    let args: Args = Args::parse();
+    let args = defaults::parse_args()?;
+
+    tracing_subscriber::fmt()
+        .with_env_filter(log_filter(&args))
+        .with_writer(io::stderr)
+        .with_target(false)
+        .without_time()
+        .init();
+
+    let result = run(&args);
+    if let Err(err) = &result {
+        if args.message_format == MessageFormat::Json {
+            let message = serde_json::json!({
+                "error": err.to_string(),
+                "context": err.chain().skip(1).map(ToString::to_string).collect::<Vec<_>>(),
+            });
+            eprintln!("{message}");
+            process::exit(1);
+        }
+    }
+    result
+}
 
-    let metadata = scarb_metadata::MetadataCommand::new()
-        .inherit_stderr()
-        .exec()?;
+/// Log filter from `--log-level`, falling back to `RUST_LOG` and then to `--verbose`.
+fn log_filter(args: &Args) -> EnvFilter {
+    if let Some(level) = args.log_level {
+        return EnvFilter::new(level.to_string());
+    }
 
-    let main_package = args.packages_filter.match_one(&metadata)?;
+    let default_level = match args.verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    EnvFilter::builder()
+        .with_default_directive(default_level.into())
+        .from_env_lossy()
+}
 
-    let project_config = get_project_config(&metadata, &main_package)?;
+fn run(args: &Args) -> Result<()> {
+    if let Some(Command::Schema) = args.command {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema::project_config_schema())?
+        );
+        return Ok(());
+    }
 
-    let mut cairo_project_toml = toml::to_string_pretty(&project_config)?;
-    cairo_project_toml.push('\n');
+    // An explicit output is known upfront, so there is no need to query Scarb for it.
+    if args.print_path {
+        if let Some(output) = &args.output {
+            print_output_path(output);
+            return Ok(());
+        }
+    }
 
-    let output = args.output.unwrap_or_else(|| {
-        metadata
-            .workspace
-            .root
-            .clone()
-            .into_std_path_buf()
-            .join("cairo_project.toml")
-    });
-    if output == Path::new("-") {
-        println!("{cairo_project_toml}");
-    } else {
-        fs::write(output, cairo_project_toml)?;
+    if args.both_profiles {
+        for profile in ["dev", "release"] {
+            let mut args = Args {
+                profile: Some(profile.to_owned()),
+                both_profiles: false,
+                ..args.clone()
+            };
+            let metadata = load_metadata(&args)?;
+            let file_name = format!("cairo_project.{profile}.{}", args.format.extension());
+            args.output = Some(OutputTarget::File(
+                metadata.workspace.root.join(file_name).into_std_path_buf(),
+            ));
+            eject(&args, metadata)?;
+        }
+        return Ok(());
     }
 
-    Ok(())
+    let metadata = load_metadata(args)?;
+    eject(args, metadata)
 }
 
-fn get_project_config(
-    metadata: &Metadata,
-    main_package: &PackageMetadata,
-) -> Result<ProjectConfigContent> {
-    let compilation_unit = metadata
+/// Generate and emit configs as requested by `args`, out of already loaded `metadata`.
+fn eject(args: &Args, metadata: Metadata) -> Result<()> {
+    if args.print_path {
+        print_output_path(&output_target(args, &metadata));
+        return Ok(());
+    }
+
+    let options = EjectOptions {
+        include_corelib: args.include_corelib,
+        target: args.target.clone(),
+        target_kind: args.target_kind.clone(),
+        validate: args.validate,
+        edition: args.edition,
+        crate_editions: args.crate_edition.iter().cloned().collect(),
+        package_version: args.package_version.clone(),
+        no_version: args.no_version,
+        relative_to: None,
+        extra_cfg: extra_cfg(args)?,
+        no_experimental_features: args.no_experimental_features,
+        validate_cfg: args.validate_cfg,
+        detect_cycles: args.detect_cycles,
+        crate_no_features: args.crate_no_features.iter().cloned().collect(),
+        root_kind: args.root_kind.into(),
+        discriminator_strategy: args.discriminator_strategy.into(),
+        check_corelib: args.check_corelib,
+        canonicalize: args.canonicalize,
+        posix_paths: args.posix_paths,
+        exclude_dev_deps: args.exclude_dev_deps,
+        only_crate: args.only_crate.clone(),
+        max_depth: args.max_depth,
+        corelib_path: args.corelib_path.clone(),
+        prune_unreferenced: args.prune_unreferenced,
+        external_roots: if args.deny_external_roots {
+            ExternalRoots::Deny
+        } else if args.warn_external_roots {
+            ExternalRoots::Warn
+        } else {
+            ExternalRoots::Allow
+        },
+        deny_root_conflicts: args.deny_root_conflicts,
+        strict: args.strict,
+        strict_edition: args.strict_edition,
+        exclude: args.exclude.clone(),
+        member_roots_only: args.include_only_target_package_roots,
+        minimize: args.minimize,
+        roots_only: args.roots_only,
+        experimental_features_override: args
+            .features_override
+            .as_deref()
+            .map(read_features_override)
+            .transpose()?
+            .unwrap_or_default(),
+    };
+
+    if let Some(Command::List { format }) = args.command {
+        return list_compilation_units(args, &metadata, &options, format);
+    }
+
+    if args.all {
+        let members = metadata
+            .packages
+            .iter()
+            .filter(|package| metadata.workspace.members.contains(&package.id))
+            .cloned()
+            .collect::<Vec<_>>();
+        return eject_per_package(args, &metadata, &members, &options);
+    }
+
+    if args.per_package {
+        let packages = args.packages_filter.match_many(&metadata)?;
+        return eject_per_package(args, &metadata, &packages, &options);
+    }
+
+    if let Some(pattern) = &args.packages_glob {
+        let packages = match_packages_glob(&metadata, pattern)?;
+        return eject_per_package(args, &metadata, &packages, &options);
+    }
+
+    let main_package = match_main_package(args, &metadata)?;
+
+    if args.explain_selection {
+        explain_selection(&metadata, &main_package, &options);
+    }
+
+    if let Some(name) = &args.inspect_crate {
+        return inspect_crate(args, &metadata, &main_package, &options, name);
+    }
+
+    if args.components_report {
+        return report_components(args, &metadata, &main_package, &options);
+    }
+
+    if args.print_selected_unit {
+        let compilation_unit = select_compilation_unit(&metadata, &main_package, &options)?;
+        println!("{}", serde_json::to_string_pretty(compilation_unit)?);
+        return Ok(());
+    }
+
+    if let Some(output_dir) = &args.output_dir {
+        return eject_split(args, &metadata, &main_package, options, output_dir);
+    }
+
+    let output = output_target(args, &metadata);
+    let options = relative_to_output(args, &metadata, &output, options);
+    let cairo_project_toml = if args.merge_targets.is_empty() {
+        render(args, &metadata, &main_package, &options)?
+    } else {
+        render_merged(args, &metadata, &main_package, &options)?
+    };
+
+    emit(args, &output, &cairo_project_toml)
+}
+
+/// Where the single generated config goes: `--output`, or the default file in the workspace root.
+fn output_target(args: &Args, metadata: &Metadata) -> OutputTarget {
+    args.output.clone().unwrap_or_else(|| {
+        if args.fragment {
+            return OutputTarget::Stdout;
+        }
+        OutputTarget::File(
+            metadata
+                .workspace
+                .root
+                .clone()
+                .into_std_path_buf()
+                .join(args.format.default_file_name()),
+        )
+    })
+}
+
+fn print_output_path(output: &OutputTarget) {
+    match output {
+        OutputTarget::Stdout => println!("-"),
+        OutputTarget::File(path) => println!("{}", path.display()),
+    }
+}
+
+/// Describe every compilation unit of `package` and how [`select_compilation_unit`] ranked it.
+fn explain_selection(metadata: &Metadata, package: &PackageMetadata, options: &EjectOptions) {
+    let selected = select_compilation_unit(metadata, package, options).ok();
+    eprintln!(
+        "compilation units of package {}, ranked by (priority, target name, id):",
+        package.name
+    );
+    for unit in metadata
         .compilation_units
         .iter()
-        .filter(|unit| unit.package == main_package.id)
-        .min_by_key(|unit| match unit.target.name.as_str() {
-            name @ "starknet-contract" => (0, name),
-            name @ "lib" => (1, name),
-            name => (2, name),
+        .filter(|unit| unit.package == package.id)
+    {
+        let verdict = if options
+            .target
+            .as_ref()
+            .is_some_and(|t| *t != unit.target.name)
+        {
+            "skipped, target name does not match --target"
+        } else if options
+            .target_kind
+            .as_ref()
+            .is_some_and(|kind| *kind != unit.target.kind)
+        {
+            "skipped, target kind does not match --target-kind"
+        } else if selected.is_some_and(|selected| selected.id == unit.id) {
+            "selected"
+        } else {
+            "candidate"
+        };
+        eprintln!(
+            "  {} ({}) [{}]: priority {}, {verdict}",
+            unit.target.name,
+            unit.target.kind,
+            unit.id,
+            selection_priority(unit)
+        );
+    }
+    if selected.is_none() {
+        eprintln!("no compilation unit was selected");
+    }
+}
+
+fn match_main_package(args: &Args, metadata: &Metadata) -> Result<PackageMetadata> {
+    if let Some(main_manifest) = &args.main_manifest {
+        return find_package_by_manifest(metadata, main_manifest);
+    }
+
+    args.packages_filter.match_one(metadata).with_context(|| {
+        let available = metadata
+            .packages
+            .iter()
+            .filter(|package| metadata.workspace.members.contains(&package.id))
+            .map(|package| package.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("failed to select package to eject, available packages: {available}")
+    })
+}
+
+/// Workspace members whose name, or directory or manifest path relative to the workspace root,
+/// matches `pattern`, see [`glob_matches`].
+fn match_packages_glob(metadata: &Metadata, pattern: &str) -> Result<Vec<PackageMetadata>> {
+    let workspace_root = metadata.workspace.root.as_std_path();
+    let relative = |path: &Path| {
+        path.strip_prefix(workspace_root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    };
+
+    let packages = metadata
+        .packages
+        .iter()
+        .filter(|package| metadata.workspace.members.contains(&package.id))
+        .filter(|package| {
+            let manifest_path = package.manifest_path.as_std_path();
+            let package_dir = manifest_path.parent().unwrap_or(manifest_path);
+            [
+                package.name.clone(),
+                relative(package_dir),
+                relative(manifest_path),
+            ]
+            .iter()
+            .any(|candidate| glob_matches(pattern.as_bytes(), candidate.as_bytes()))
         })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if packages.is_empty() {
+        bail!("no workspace member matches `{pattern}`");
+    }
+    Ok(packages)
+}
+
+/// Match `text` against a glob where `*` matches any run of characters other than `/`
+/// and `?` matches exactly one such character.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, _) => text.is_empty(),
+        (Some(b'*'), _) => {
+            glob_matches(&pattern[1..], text)
+                || (text.first().is_some_and(|&c| c != b'/') && glob_matches(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(&c)) if c != b'/' => glob_matches(&pattern[1..], &text[1..]),
+        (Some(p), Some(c)) if p == c => glob_matches(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+/// Find the workspace member whose `Scarb.toml` is `manifest_path`, comparing canonical paths if possible.
+fn find_package_by_manifest(metadata: &Metadata, manifest_path: &Path) -> Result<PackageMetadata> {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let wanted = canonical(manifest_path);
+
+    metadata
+        .packages
+        .iter()
+        .filter(|package| metadata.workspace.members.contains(&package.id))
+        .find(|package| canonical(package.manifest_path.as_std_path()) == wanted)
+        .cloned()
         .ok_or_else(|| {
             anyhow!(
-                "could not find a compilation unit suitable for ejection for package {}",
-                main_package.id
+                "no workspace member with manifest {}",
+                manifest_path.display()
             )
-        })?;
+        })
+}
+
+fn load_metadata(args: &Args) -> Result<Metadata> {
+    let metadata = match &args.metadata_file {
+        Some(metadata_file) => read_metadata_file(metadata_file)?,
+        None => match &args.metadata_cache {
+            Some(cache) => load_cached_metadata(args, cache)?,
+            None => run_metadata_command(args)?,
+        },
+    };
+
+    if let Some(profile) = &args.profile {
+        if metadata.current_profile != *profile {
+            bail!(
+                "metadata was generated for profile `{}`, not `{profile}`\n\
+                help: available profiles: {}",
+                metadata.current_profile,
+                metadata.profiles.join(", ")
+            );
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn run_metadata_command(args: &Args) -> Result<Metadata> {
+    let mut command = scarb_metadata::MetadataCommand::new();
+    command.inherit_stderr();
+
+    if let Some(manifest_path) = &args.manifest_path {
+        if manifest_path.file_name() != Some("Scarb.toml".as_ref()) {
+            bail!(
+                "manifest path must point to a `Scarb.toml` file: {}",
+                manifest_path.display()
+            );
+        }
+        if !manifest_path.is_file() {
+            bail!("manifest path does not exist: {}", manifest_path.display());
+        }
+        command.manifest_path(manifest_path);
+    }
 
-    let crate_roots = get_crate_roots(compilation_unit);
-    let crates_config = get_crates_config(metadata, compilation_unit, main_package);
+    if let Some(profile) = &args.profile {
+        command.profile(profile);
+    }
+
+    if args.offline || args.frozen {
+        command.env("SCARB_OFFLINE", "true");
+    }
+
+    if !args.frozen {
+        return Ok(command.exec()?);
+    }
+
+    let lockfiles = snapshot_lockfiles(args)?;
+    let metadata = command
+        .exec()
+        .context("`scarb metadata` failed, note that `--frozen` forbids network access")?;
+
+    let lockfile = metadata
+        .workspace
+        .root
+        .join("Scarb.lock")
+        .into_std_path_buf();
+    let before = lockfiles
+        .into_iter()
+        .find(|(path, _)| *path == lockfile)
+        .map(|(_, content)| content);
+    if before != fs::read(&lockfile).ok() {
+        // Scarb has already written the lockfile by now, so put the original one back.
+        let restored = match &before {
+            Some(content) => fs::write(&lockfile, content),
+            None => fs::remove_file(&lockfile),
+        };
+        if let Err(e) = restored {
+            warn!("failed to restore {}: {e}", lockfile.display());
+        }
+        bail!(
+            "{} needs to be updated, but `--frozen` was passed",
+            lockfile.display()
+        );
+    }
+
+    Ok(metadata)
+}
+
+/// Load metadata from `cache` if it is still fresh, or run `scarb metadata` and store the result.
+///
+/// The cache is keyed by modification times of all workspace manifests and the lockfile, so
+/// touching any of them invalidates it.
+fn load_cached_metadata(args: &Args, cache: &Path) -> Result<Metadata> {
+    let cached = fs::read_to_string(cache)
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .and_then(|mut entry| {
+            let key = entry.get("key")?.as_u64()?;
+            let metadata: Metadata =
+                serde_json::from_value(entry.get_mut("metadata")?.take()).ok()?;
+            Some((key, metadata))
+        });
+
+    if let Some((key, metadata)) = cached {
+        if key == metadata_cache_key(args, &metadata) {
+            debug!("using cached metadata from {}", cache.display());
+            return Ok(metadata);
+        }
+        debug!("cached metadata in {} is stale", cache.display());
+    }
+
+    let metadata = run_metadata_command(args)?;
+    let entry = serde_json::json!({
+        "key": metadata_cache_key(args, &metadata),
+        "metadata": metadata,
+    });
+    fs::write(cache, entry.to_string())
+        .with_context(|| format!("failed to write metadata cache: {}", cache.display()))?;
+
+    Ok(metadata)
+}
+
+fn metadata_cache_key(args: &Args, metadata: &Metadata) -> u64 {
+    let manifests = metadata
+        .packages
+        .iter()
+        .filter(|package| metadata.workspace.members.contains(&package.id))
+        .map(|package| package.manifest_path.clone().into_std_path_buf())
+        .chain([
+            metadata.workspace.manifest_path.clone().into_std_path_buf(),
+            metadata
+                .workspace
+                .root
+                .join("Scarb.lock")
+                .into_std_path_buf(),
+        ]);
+
+    let mut hasher = DefaultHasher::new();
+    (&args.manifest_path, &args.profile).hash(&mut hasher);
+    for path in manifests {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        (path, modified).hash(&mut hasher);
+    }
+    hasher.finish()
+}
 
-    Ok(ProjectConfigContent {
-        crate_roots,
-        crates_config,
+/// Read all `Scarb.lock` files that may belong to the workspace, before Scarb gets to touch them.
+fn snapshot_lockfiles(args: &Args) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let start = match &args.manifest_path {
+        Some(manifest_path) => manifest_path.parent().unwrap_or(Path::new(".")),
+        None => Path::new("."),
+    };
+    let start = std::path::absolute(start)?;
+
+    Ok(start
+        .ancestors()
+        .map(|dir| dir.join("Scarb.lock"))
+        .filter_map(|path| fs::read(&path).ok().map(|content| (path, content)))
+        .collect())
+}
+
+/// Parse metadata previously exported with `scarb metadata`.
+///
+/// The schema version must match [`scarb_metadata::VersionPin`].
+fn read_metadata_file(path: &Path) -> Result<Metadata> {
+    let json = if path == Path::new("-") {
+        io::read_to_string(io::stdin()).context("failed to read metadata from standard input")?
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("failed to read metadata file: {}", path.display()))?
+    };
+
+    let expected = scarb_metadata::VersionPin.numeric();
+    let json: serde_json::Value =
+        serde_json::from_str(&json).context("metadata file is not valid JSON")?;
+    match json.get("version") {
+        Some(version) if version.as_u64() == Some(expected) => {}
+        Some(version) => bail!(
+            "metadata format version {version} is not supported, expected {expected}\n\
+            help: regenerate it with `scarb metadata --format-version {expected}`"
+        ),
+        None => bail!(
+            "metadata has no format version, expected output of \
+            `scarb metadata --format-version {expected}`"
+        ),
+    }
+
+    serde_json::from_value(json).with_context(|| {
+        format!(
+            "failed to deserialize metadata, expected output of \
+            `scarb metadata --format-version {expected}`"
+        )
     })
 }
 
-fn get_crate_roots(
-    compilation_unit: &CompilationUnitMetadata,
-) -> OrderedHashMap<CrateIdentifier, PathBuf> {
-    compilation_unit
-        .components
+fn read_features_override(path: &Path) -> Result<BTreeMap<String, bool>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read features override file: {}", path.display()))?;
+    toml::from_str(&contents).with_context(|| {
+        format!(
+            "failed to parse features override file, expected a table of booleans: {}",
+            path.display()
+        )
+    })
+}
+
+/// Print all compilation units of the selected package, marking the one that would be ejected.
+fn list_compilation_units(
+    args: &Args,
+    metadata: &Metadata,
+    options: &EjectOptions,
+    format: ListFormat,
+) -> Result<()> {
+    let main_package = match_main_package(args, metadata)?;
+    let selected = select_compilation_unit(metadata, &main_package, options).ok();
+
+    let units = metadata
+        .compilation_units
         .iter()
-        .filter(|c| c.name != CORELIB_CRATE_NAME)
-        .map(|c| {
+        .filter(|unit| unit.package == main_package.id)
+        .map(|unit| {
             (
-                c.id.clone().unwrap_or(c.name.clone().into()).into(),
-                c.source_root().into(),
+                unit,
+                selected.is_some_and(|selected| selected.id == unit.id),
             )
-        })
-        .collect()
+        });
+
+    match format {
+        ListFormat::Human => {
+            for (unit, is_selected) in units {
+                println!(
+                    "{} [{}] ({} components){}",
+                    unit.target.name,
+                    unit.id.repr,
+                    unit.components.len(),
+                    if is_selected { " [selected]" } else { "" }
+                );
+            }
+        }
+        ListFormat::Json => {
+            let units = units
+                .map(|(unit, is_selected)| {
+                    serde_json::json!({
+                        "id": unit.id,
+                        "target": unit.target.name,
+                        "components": unit.components.len(),
+                        "selected": is_selected,
+                    })
+                })
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&units)?);
+        }
+    }
+
+    Ok(())
 }
 
-fn get_crates_config(
+/// Write a minimal config per crate, each containing only its root and settings.
+fn eject_split(
+    args: &Args,
     metadata: &Metadata,
-    compilation_unit: &CompilationUnitMetadata,
-    main_package: &PackageMetadata,
-) -> AllCratesConfig {
-    let global_crate_settings = get_global_crate_settings(compilation_unit, main_package);
-    let override_map = compilation_unit
-        .components
-        .iter()
-        .filter(|c| c.name != CORELIB_CRATE_NAME)
-        .map(|component| {
-            (
-                component
-                    .id
+    package: &PackageMetadata,
+    options: EjectOptions,
+    output_dir: &Path,
+) -> Result<()> {
+    if !args.dry_run && !args.check && !args.diff {
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("failed to create {}", output_dir.display()))?;
+    }
+
+    let options = relative_to_dir(args, metadata, output_dir.to_path_buf(), options);
+    let (project_config, compilation_unit) = generate(metadata, package, &options)?;
+    let header = header(args, metadata, &compilation_unit.target.name);
+    let name_of = |id: &CrateIdentifier| {
+        project_config
+            .crates_config
+            .override_map
+            .get(id)
+            .and_then(|settings| settings.name.clone())
+            .unwrap_or_else(|| SmolStr::from(id.clone()))
+    };
+
+    // Check before writing anything, so a clash never leaves a half-written directory behind.
+    let mut ids_by_name = HashMap::new();
+    for id in project_config.crate_roots.keys() {
+        if let Some(other) = ids_by_name.insert(name_of(id), id) {
+            bail!(
+                "crates `{}` and `{}` are both named `{}`, so their configs would overwrite each \
+                other in {}",
+                SmolStr::from(other.clone()),
+                SmolStr::from(id.clone()),
+                name_of(id),
+                output_dir.display()
+            );
+        }
+    }
+
+    for (id, root) in project_config.crate_roots.iter() {
+        let settings = project_config.crates_config.override_map.get(id);
+        let name = name_of(id);
+
+        let crate_config = ProjectConfigContent {
+            crate_roots: [(id.clone(), root.clone())].into_iter().collect(),
+            crates_config: AllCratesConfig {
+                global: project_config.crates_config.global.clone(),
+                override_map: settings
+                    .map(|settings| (id.clone(), settings.clone()))
+                    .into_iter()
+                    .collect(),
+            },
+        };
+
+        let output =
+            OutputTarget::File(output_dir.join(format!("{name}.{}", args.format.extension())));
+        let content = serialize(args, metadata, &crate_config, &header, &name)?;
+        emit(args, &output, &content)?;
+    }
+
+    Ok(())
+}
+
+/// Eject each of `packages` into its own package root, next to its `Scarb.toml`.
+fn eject_per_package(
+    args: &Args,
+    metadata: &Metadata,
+    packages: &[PackageMetadata],
+    options: &EjectOptions,
+) -> Result<()> {
+    let to_stdout = match &args.output {
+        None => false,
+        Some(OutputTarget::Stdout) => true,
+        Some(OutputTarget::File(_)) => {
+            bail!("`--all` and `--per-package` only support `-` as `--output`")
+        }
+    };
+
+    let mut written = Vec::new();
+    // Consecutive JSON documents do not form valid JSON, so they are printed as a single object
+    // keyed by package name instead.
+    let mut documents = serde_json::Map::new();
+    let mut crate_roots = 0;
+    for package in packages {
+        info!("processing package {}", package.name);
+        let output = if to_stdout {
+            OutputTarget::Stdout
+        } else {
+            OutputTarget::File(
+                package
+                    .root
                     .clone()
-                    .unwrap_or(component.name.clone().into())
-                    .into(),
-                get_crate_settings_for_component(component, compilation_unit, metadata),
+                    .into_std_path_buf()
+                    .join(args.format.default_file_name()),
             )
-        })
-        .collect();
+        };
+
+        let options = relative_to_output(args, metadata, &output, options.clone());
+        let (project_config, compilation_unit) = generate(metadata, package, &options)?;
+        let cairo_project_toml = serialize(
+            args,
+            metadata,
+            &project_config,
+            &header(args, metadata, &compilation_unit.target.name),
+            &package.name,
+        )?;
+        crate_roots += project_config.crate_roots.len();
+
+        match output {
+            OutputTarget::Stdout if args.format == OutputFormat::Json => {
+                let document = serde_json::from_str(&cairo_project_toml)
+                    .context("generated config is not valid JSON")?;
+                documents.insert(package.name.clone(), document);
+            }
+            OutputTarget::Stdout => {
+                println!("# package: {}", package.name);
+                println!("{cairo_project_toml}");
+            }
+            OutputTarget::File(ref path) => {
+                emit(args, &output, &cairo_project_toml)?;
+                written.push(path.clone());
+            }
+        }
+    }
+
+    if !documents.is_empty() {
+        println!("{}", serde_json::to_string_pretty(&documents)?);
+    }
+
+    info!(
+        "processed {} packages, {crate_roots} crate roots total",
+        packages.len()
+    );
+
+    if !to_stdout && !args.check && !args.diff && !args.dry_run {
+        eprintln!("wrote {} files:", written.len());
+        for output in written {
+            eprintln!("  {}", output.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Make crate roots relative according to `--relative` or `--relative-to`, where `output` is
+/// the output file.
+///
+/// Standard output has no directory of its own, so the workspace root is used instead.
+fn relative_to_output(
+    args: &Args,
+    metadata: &Metadata,
+    output: &OutputTarget,
+    options: EjectOptions,
+) -> EjectOptions {
+    let base = match output {
+        OutputTarget::Stdout => metadata.workspace.root.clone().into_std_path_buf(),
+        OutputTarget::File(output) => output
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+    };
+
+    relative_to_dir(args, metadata, base, options)
+}
 
-    AllCratesConfig {
-        global: global_crate_settings,
-        override_map,
+/// Make crate roots relative according to `--relative` or `--relative-to`, where `output_dir`
+/// is the directory output is written to.
+fn relative_to_dir(
+    args: &Args,
+    metadata: &Metadata,
+    output_dir: PathBuf,
+    options: EjectOptions,
+) -> EjectOptions {
+    let base = match &args.relative_to {
+        None if !args.relative => return options,
+        None | Some(RelativeBase::Output) => output_dir,
+        Some(RelativeBase::Workspace) => metadata.workspace.root.clone().into_std_path_buf(),
+        Some(RelativeBase::Dir(dir)) => dir.clone(),
+    };
+
+    EjectOptions {
+        relative_to: Some(base),
+        ..options
     }
 }
 
-fn get_global_crate_settings(
-    compilation_unit: &CompilationUnitMetadata,
+/// Generate the project config for a package and serialize it in the requested format.
+fn render(
+    args: &Args,
+    metadata: &Metadata,
     package: &PackageMetadata,
-) -> CrateSettings {
-    let edition = get_edition(&Some(package), package.name.as_str());
-    let version = package.version.clone();
-    let cfg_set = get_cairo_cfg_set(&compilation_unit.cfg, package.name.as_str());
-    let dependencies = compilation_unit
+    options: &EjectOptions,
+) -> Result<String> {
+    let (project_config, compilation_unit) = generate(metadata, package, options)?;
+    serialize(
+        args,
+        metadata,
+        &project_config,
+        &header(args, metadata, &compilation_unit.target.name),
+        &package.name,
+    )
+}
+
+/// Generate and merge the project configs of all `--merge-targets` units of a package.
+fn render_merged(
+    args: &Args,
+    metadata: &Metadata,
+    package: &PackageMetadata,
+    options: &EjectOptions,
+) -> Result<String> {
+    let mut merged: Option<ProjectConfigContent> = None;
+    for target in &args.merge_targets {
+        let options = EjectOptions {
+            target: Some(target.clone()),
+            ..options.clone()
+        };
+        let (project_config, _) = generate(metadata, package, &options)?;
+        match &mut merged {
+            Some(merged) => merge_project_config(merged, project_config),
+            None => merged = Some(project_config),
+        }
+    }
+
+    let merged = merged.context("no targets to merge")?;
+    serialize(
+        args,
+        metadata,
+        &merged,
+        &header(args, metadata, &args.merge_targets.join(", ")),
+        &package.name,
+    )
+}
+
+fn generate<'a>(
+    metadata: &'a Metadata,
+    package: &PackageMetadata,
+    options: &EjectOptions,
+) -> Result<(ProjectConfigContent, &'a CompilationUnitMetadata)> {
+    let compilation_unit = select_compilation_unit(metadata, package, options)?;
+
+    report_discriminators(compilation_unit);
+
+    let project_config = get_project_config(metadata, package, options)?;
+
+    Ok((project_config, compilation_unit))
+}
+
+/// Comment lines put at the top of generated TOML files, unless `--no-header` is passed.
+fn header(args: &Args, metadata: &Metadata, target: &str) -> String {
+    if args.no_header || args.fragment || args.format != OutputFormat::Toml {
+        return String::new();
+    }
+
+    let mut header = format!(
+        "# generated by scarb-eject from target: {target}\n\
+        # cairo: {}\n",
+        cairo_version()
+    );
+    if args.with_lockfile_hash {
+        match lockfile_hash(metadata) {
+            Some(hash) => header.push_str(&format!("{LOCKFILE_HASH_PREFIX}{hash}\n")),
+            None => warn!("no Scarb.lock in the workspace root, leaving out its hash"),
+        }
+    }
+    header
+}
+
+const LOCKFILE_HASH_PREFIX: &str = "# scarb-lock: ";
+
+/// Hash of the workspace `Scarb.lock`, ignoring line endings, if it exists.
+fn lockfile_hash(metadata: &Metadata) -> Option<String> {
+    let lockfile = fs::read_to_string(metadata.workspace.root.join("Scarb.lock")).ok()?;
+    let hash = stable_hash(lockfile.replace("\r\n", "\n").as_bytes());
+    Some(format!("{hash:016x}"))
+}
+
+fn serialize(
+    args: &Args,
+    metadata: &Metadata,
+    project_config: &ProjectConfigContent,
+    header: &str,
+    name: &str,
+) -> Result<String> {
+    let project_config = &canonical::sorted(project_config);
+
+    // Crate settings are optional when parsing, so leave the defaulted ones out entirely.
+    let value = if args.roots_only {
+        serde_json::json!({ "crate_roots": project_config.crate_roots })
+    } else {
+        serde_json::to_value(project_config)?
+    };
+    let mut cairo_project_toml = match args.format {
+        OutputFormat::Toml => {
+            let mut cairo_project_toml = canonical::to_toml_string(value)?;
+            if args.strip_empty_sections {
+                cairo_project_toml = canonical::strip_empty_sections(&cairo_project_toml)?;
+            }
+            if args.annotate {
+                cairo_project_toml = annotate_crate_roots(&cairo_project_toml, metadata);
+            }
+            cairo_project_toml
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(&value)?,
+    };
+    cairo_project_toml.insert_str(0, header);
+    cairo_project_toml.push('\n');
+
+    if let Some(formatter) = &args.format_with {
+        cairo_project_toml = format_with(formatter, &cairo_project_toml)?;
+    }
+
+    if args.self_check {
+        let parsed: ProjectConfigContent = match args.format {
+            OutputFormat::Toml => toml::from_str(&cairo_project_toml)
+                .context("failed to parse generated config back")?,
+            OutputFormat::Json => serde_json::from_str(&cairo_project_toml)
+                .context("failed to parse generated config back")?,
+        };
+        if parsed != *project_config {
+            bail!("generated config for {name} does not round-trip");
+        }
+    }
+
+    Ok(cairo_project_toml)
+}
+
+const ANNOTATION_PREFIX: &str = " # from: ";
+
+/// Append the owning package to each `[crate_roots]` entry, as a trailing comment.
+fn annotate_crate_roots(cairo_project_toml: &str, metadata: &Metadata) -> String {
+    let packages = metadata
+        .compilation_units
+        .iter()
+        .flat_map(|unit| &unit.components)
+        .map(|c| (SmolStr::from(crate_identifier(c)), &c.package))
+        .collect::<HashMap<_, _>>();
+
+    let mut in_crate_roots = false;
+    let mut annotated = String::new();
+    for line in cairo_project_toml.lines() {
+        annotated.push_str(line);
+        if line.starts_with('[') {
+            in_crate_roots = line == "[crate_roots]";
+        } else if in_crate_roots {
+            let package = toml::from_str::<toml::Table>(line)
+                .ok()
+                .and_then(|entry| entry.keys().next().cloned())
+                .and_then(|id| packages.get(id.as_str()).copied());
+            // Package ids already spell out both the name and version of the package.
+            if let Some(package) = package {
+                annotated.push_str(&format!("{ANNOTATION_PREFIX}{package}"));
+            }
+        }
+        annotated.push('\n');
+    }
+    annotated
+}
+
+fn inspect_crate(
+    args: &Args,
+    metadata: &Metadata,
+    package: &PackageMetadata,
+    options: &EjectOptions,
+    name: &str,
+) -> Result<()> {
+    let compilation_unit = select_compilation_unit(metadata, package, options)?;
+    let component = compilation_unit
         .components
         .iter()
-        .filter(|c| c.name != CORELIB_CRATE_NAME)
-        .map(|c| {
-            (
-                c.name.clone(),
-                DependencySettings {
-                    discriminator: c.discriminator.clone().map(Into::into),
-                },
-            )
-        })
-        .collect();
-    let experimental_features = get_experimental_features(Some(package));
+        .find(|c| c.name == name)
+        .ok_or_else(|| EjectError::CrateNotFound {
+            name: name.to_owned(),
+            compilation_unit: compilation_unit.id.clone(),
+        })?;
+
+    let settings = get_crate_settings_for_component(component, compilation_unit, metadata, options);
+    let value = serde_json::to_value(settings)?;
+    match args.format {
+        OutputFormat::Toml => print!("{}", canonical::to_toml_string(value)?),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value)?),
+    }
+
+    Ok(())
+}
 
-    CrateSettings {
-        name: None,
-        edition,
-        version: Some(version),
-        cfg_set,
-        dependencies,
-        experimental_features,
+/// Pipe `content` through `formatter`, a program followed by whitespace-separated arguments.
+fn format_with(formatter: &str, content: &str) -> Result<String> {
+    let mut words = formatter.split_whitespace();
+    let program = words
+        .next()
+        .context("`--format-with` command must not be empty")?;
+    let mut child = process::Command::new(program)
+        .args(words)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run formatter `{formatter}`"))?;
+
+    // Feed stdin from another thread, so a formatter writing before it reads all input cannot
+    // deadlock on a full pipe.
+    let mut stdin = child.stdin.take().expect("stdin of the formatter is piped");
+    let content = content.to_owned();
+    let writer = std::thread::spawn(move || stdin.write_all(content.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("failed to run formatter `{formatter}`"))?;
+    writer
+        .join()
+        .expect("writing to the formatter does not panic")
+        .with_context(|| format!("failed to pass the config to formatter `{formatter}`"))?;
+    if !output.status.success() {
+        bail!("formatter `{formatter}` failed with {}", output.status);
     }
+    String::from_utf8(output.stdout)
+        .with_context(|| format!("formatter `{formatter}` did not output valid UTF-8"))
 }
 
-fn get_crate_settings_for_component(
-    component: &CompilationUnitComponentMetadata,
-    compilation_unit: &CompilationUnitMetadata,
+fn report_components(
+    args: &Args,
     metadata: &Metadata,
-) -> CrateSettings {
-    let package = metadata
-        .packages
+    package: &PackageMetadata,
+    options: &EjectOptions,
+) -> Result<()> {
+    let compilation_unit = select_compilation_unit(metadata, package, options)?;
+    let options = EjectOptions {
+        include_corelib: true,
+        ..options.clone()
+    };
+    let crates_config = get_crates_config(metadata, compilation_unit, package, &options);
+
+    let rows = compilation_unit
+        .components
         .iter()
-        .find(|package| package.id == component.package);
-    let edition = get_edition(&package, component.name.as_str());
-    let version = package.map(|p| p.version.clone());
-    let cfg_set = component
-        .cfg
-        .as_ref()
-        .and_then(|cfg| get_cairo_cfg_set(cfg, component.name.as_str()));
-    let dependencies = component
-        .dependencies
-        .as_ref()
-        .unwrap_or(&vec![])
+        .map(|component| {
+            let settings = crates_config.override_map.get(&crate_identifier(component));
+            serde_json::json!({
+                "name": component.name,
+                "package": component.package,
+                "source_root": component.source_root(),
+                "discriminator": component.discriminator,
+                "edition": settings.map(|settings| settings.edition),
+                "dependencies": component.dependencies.as_ref().map(Vec::len),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if args.format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    const COLUMNS: [&str; 6] = [
+        "name",
+        "package",
+        "source_root",
+        "discriminator",
+        "edition",
+        "dependencies",
+    ];
+    let cells = rows
         .iter()
-        .filter_map(|CompilationUnitComponentDependencyMetadata { id, .. }| {
-            compilation_unit
-                .components
-                .iter()
-                .filter(|c| c.name != CORELIB_CRATE_NAME)
-                .find(|c| c.id.as_ref() == Some(id))
-                .map(|c| {
-                    (
-                        c.name.clone(),
-                        DependencySettings {
-                            discriminator: c.discriminator.clone().map(Into::into),
-                        },
-                    )
-                })
+        .map(|row| {
+            COLUMNS.map(|column| match &row[column] {
+                serde_json::Value::Null => "-".to_owned(),
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            })
         })
-        .collect();
-    let experimental_features = get_experimental_features(package);
-
-    CrateSettings {
-        name: Some(component.name.clone().into()),
-        edition,
-        version,
-        cfg_set,
-        dependencies,
-        experimental_features,
-    }
-}
-
-/// Get the [`Edition`] from [`PackageMetadata`], or assume the default edition.
-fn get_edition(package: &Option<&PackageMetadata>, crate_name: &str) -> Edition {
-    package
-        .and_then(|p| p.edition.clone())
-        .and_then(|e| {
-            serde_json::from_value(e.into())
-                .with_context(|| format!("failed to parse edition of package: {crate_name}"))
-                .inspect_err(|e| warn!("{e:?}"))
-                .ok()
+        .collect::<Vec<_>>();
+    let widths = cells.iter().fold(COLUMNS.map(str::len), |mut widths, row| {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+        widths
+    });
+    for row in std::iter::once(COLUMNS.map(ToOwned::to_owned)).chain(cells) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+fn report_discriminators(compilation_unit: &CompilationUnitMetadata) {
+    for component in &compilation_unit.components {
+        info!(
+            "{}: discriminator {}",
+            component.name,
+            component.discriminator.as_deref().unwrap_or("none")
+        );
+    }
+}
+
+/// Write, check or diff the generated content against `output`, depending on flags.
+fn emit(args: &Args, output: &OutputTarget, cairo_project_toml: &str) -> Result<()> {
+    let output = match output {
+        OutputTarget::Stdout if args.check => {
+            bail!("`--check` requires writing to a file, not to standard output")
+        }
+        OutputTarget::Stdout if args.diff => {
+            bail!("`--diff` requires writing to a file, not to standard output")
+        }
+        OutputTarget::Stdout if args.fragment => {
+            println!("{}", cairo_project_toml.trim());
+            return Ok(());
+        }
+        OutputTarget::Stdout => {
+            println!("{cairo_project_toml}");
+            return Ok(());
+        }
+        OutputTarget::File(output) => output,
+    };
+
+    let cairo_project_toml = if args.preserve_comments && args.format == OutputFormat::Toml {
+        leading_comments(output) + cairo_project_toml
+    } else {
+        cairo_project_toml.to_owned()
+    };
+    let mut cairo_project_toml = args.line_ending.apply(&cairo_project_toml);
+    if args.output_encoding == OutputEncoding::Utf8Bom {
+        cairo_project_toml.insert(0, BOM);
+    }
+    let cairo_project_toml = &cairo_project_toml;
+
+    if args.check {
+        check_up_to_date(output, cairo_project_toml)?;
+    } else if args.diff {
+        print_diff(output, cairo_project_toml)?;
+    } else if args.dry_run {
+        eprintln!(
+            "would write {} bytes to {}",
+            cairo_project_toml.len(),
+            output.display()
+        );
+    } else {
+        write_output(args, output, cairo_project_toml)?;
+        if args.git_add {
+            git_add(output);
+        }
+    }
+
+    Ok(())
+}
+
+/// Comment lines at the top of `output` before its first table, except the ones of [`header`].
+fn leading_comments(output: &Path) -> String {
+    let Ok(existing) = fs::read_to_string(output) else {
+        return String::new();
+    };
+
+    existing
+        .trim_start_matches(BOM)
+        .lines()
+        .take_while(|line| line.trim().is_empty() || line.trim_start().starts_with('#'))
+        .filter(|line| line.trim_start().starts_with('#'))
+        .filter(|line| {
+            !line.starts_with("# generated by scarb-eject ")
+                && !line.starts_with("# cairo: ")
+                && !line.starts_with(LOCKFILE_HASH_PREFIX)
         })
-        .unwrap_or_default()
+        .map(|line| format!("{line}\n"))
+        .collect()
 }
 
-/// Convert a slice of [`scarb_metadata::Cfg`]s to a [`cairo_lang_filesystem::cfg::CfgSet`].
-///
-/// The conversion is done the same way as in Scarb (except no panicking):
-/// <https://github.com/software-mansion/scarb/blob/9fe97c8eb8620a1e2103e7f5251c5a9189e75716/scarb/src/ops/metadata.rs#L295-L302>
-fn get_cairo_cfg_set(cfg_set: &[scarb_metadata::Cfg], crate_name: &str) -> Option<CfgSet> {
-    serde_json::to_value(cfg_set)
-        .and_then(serde_json::from_value)
-        .with_context(|| {
+/// Stage `output` with `git add`, warning instead of failing if git or a repository is missing.
+fn git_add(output: &Path) {
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let git = |args: &[&OsStr]| {
+        process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .status()
+    };
+
+    match git(&["rev-parse".as_ref(), "--is-inside-work-tree".as_ref()]) {
+        Err(e) => {
+            warn!("could not run git, not staging {}: {e}", output.display());
+            return;
+        }
+        Ok(status) if !status.success() => {
+            warn!(
+                "{} is not in a git repository, not staging it",
+                dir.display()
+            );
+            return;
+        }
+        Ok(_) => {}
+    }
+
+    let file_name = output.file_name().unwrap_or(output.as_os_str());
+    match git(&["add".as_ref(), "--".as_ref(), file_name]) {
+        Ok(status) if status.success() => debug!("staged {}", output.display()),
+        Ok(status) => warn!("`git add` of {} failed with {status}", output.display()),
+        Err(e) => warn!("could not run git, not staging {}: {e}", output.display()),
+    }
+}
+
+fn write_output(args: &Args, output: &Path, cairo_project_toml: &str) -> Result<()> {
+    match args.overwrite_policy {
+        OverwritePolicy::Never if output.exists() => bail!(
+            "{} already exists\n\
+            help: pass `--overwrite-policy always` to overwrite it",
+            output.display()
+        ),
+        OverwritePolicy::IfChanged
+            if fs::read(output).is_ok_and(|existing| existing == cairo_project_toml.as_bytes()) =>
+        {
+            eprintln!("{} unchanged", output.display());
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if args.backup && output.exists() {
+        let mut backup = output.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(output, &backup).with_context(|| {
             format!(
-                "scarb metadata cfg did not convert identically to cairo one for crate: {crate_name}"
+                "failed to back up {} to {}",
+                output.display(),
+                Path::new(&backup).display()
             )
-        })
-        .inspect_err(|e| warn!("{e:?}"))
-        .ok()
+        })?;
+    }
+
+    // Write to a sibling file first, so an interruption never leaves a truncated output behind.
+    let mut temp = output.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    fs::write(&temp, cairo_project_toml)
+        .with_context(|| format!("failed to write {}", temp.display()))?;
+    fs::rename(&temp, output)
+        .with_context(|| format!("failed to move {} to {}", temp.display(), output.display()))?;
+
+    Ok(())
 }
 
-/// Get [`ExperimentalFeaturesConfig`] from [`PackageMetadata`] fields.
-fn get_experimental_features(package: Option<&PackageMetadata>) -> ExperimentalFeaturesConfig {
-    let contains = |feature: &str| -> bool {
-        package
-            .map(|p| p.experimental_features.contains(&feature.into()))
-            .unwrap_or(false)
+const BOM: char = '\u{feff}';
+
+/// Compare the generated content with the file on disk, ignoring byte order marks, line endings
+/// and trailing newlines.
+fn check_up_to_date(output: &Path, generated: &str) -> Result<()> {
+    let existing = fs::read_to_string(output)
+        .with_context(|| format!("failed to read {}", output.display()))?;
+
+    let lockfile_stamp = |content: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(LOCKFILE_HASH_PREFIX))
+            .map(str::to_owned)
     };
+    if let Some(generated_stamp) = lockfile_stamp(generated) {
+        if lockfile_stamp(&existing).is_some_and(|stamp| stamp != generated_stamp) {
+            bail!(
+                "{} was generated for a different Scarb.lock\n\
+                help: run `scarb eject` to regenerate it",
+                output.display()
+            );
+        }
+    }
 
-    ExperimentalFeaturesConfig {
-        negative_impls: contains("negative_impls"),
-        associated_item_constraints: contains("associated_item_constraints"),
-        coupons: contains("coupons"),
+    let normalize = |content: &str| {
+        content
+            .trim_start_matches(BOM)
+            .replace("\r\n", "\n")
+            .lines()
+            .map(|line| {
+                line.split_once(ANNOTATION_PREFIX)
+                    .map_or(line, |(line, _)| line)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end_matches('\n')
+            .to_owned()
+    };
+    if normalize(&existing) != normalize(generated) {
+        bail!(
+            "{} is out of date\n\
+            help: run `scarb eject` to regenerate it",
+            output.display()
+        );
     }
+
+    Ok(())
+}
+
+/// Print a unified diff between the file on disk and the generated content.
+///
+/// A missing file is treated as empty, so the whole content is shown as added.
+fn print_diff(output: &Path, generated: &str) -> Result<()> {
+    let existing = if output.exists() {
+        fs::read_to_string(output)
+            .with_context(|| format!("failed to read {}", output.display()))?
+    } else {
+        String::new()
+    };
+
+    let path = output.display().to_string();
+    let diff = TextDiff::from_lines(existing.as_str(), generated);
+    print!(
+        "{}",
+        diff.unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{path}"), &format!("b/{path}"))
+    );
+
+    Ok(())
 }
@@ -0,0 +1,108 @@
+//! Handwritten JSON Schema of `cairo_project.toml`, matching the serde representation of
+//! [`ProjectConfigContent`](scarb_eject::ProjectConfigContent).
+
+use cairo_lang_filesystem::db::ExperimentalFeaturesConfig;
+use serde_json::{json, Map, Value};
+
+use crate::EDITIONS;
+
+/// Build a JSON Schema (draft 2020-12) describing `ProjectConfigContent`.
+pub fn project_config_schema() -> Value {
+    let editions = EDITIONS
+        .iter()
+        .map(|edition| serde_json::to_value(edition).expect("editions serialize to strings"))
+        .collect::<Vec<_>>();
+
+    let experimental_features = match serde_json::to_value(ExperimentalFeaturesConfig::default()) {
+        Ok(Value::Object(fields)) => fields
+            .into_iter()
+            .map(|(name, _)| (name, json!({ "type": "boolean" })))
+            .collect::<Map<_, _>>(),
+        _ => unreachable!("experimental features config always serializes to an object"),
+    };
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "ProjectConfigContent",
+        "description": "Contents of a Cairo project config file, `cairo_project.toml`.",
+        "type": "object",
+        "required": ["crate_roots"],
+        "properties": {
+            "crate_roots": {
+                "description": "Root directory or file of every crate, keyed by crate identifier.",
+                "type": "object",
+                "additionalProperties": { "type": "string" },
+            },
+            "config": { "$ref": "#/$defs/AllCratesConfig" },
+        },
+        "$defs": {
+            "AllCratesConfig": {
+                "description": "Additional configurations for all crates.",
+                "type": "object",
+                "properties": {
+                    "global": { "$ref": "#/$defs/CrateSettings" },
+                    "override": {
+                        "description": "Configuration override per crate identifier.",
+                        "type": "object",
+                        "additionalProperties": { "$ref": "#/$defs/CrateSettings" },
+                    },
+                },
+            },
+            "CrateSettings": {
+                "description": "Configuration of a single crate.",
+                "type": "object",
+                "required": ["edition"],
+                "properties": {
+                    "name": {
+                        "description": "Name the crate is referred to by in Cairo code.",
+                        "type": ["string", "null"],
+                    },
+                    "edition": {
+                        "description": "Cairo edition of the crate.",
+                        "enum": editions,
+                    },
+                    "version": {
+                        "description": "Semantic version of the crate.",
+                        "type": ["string", "null"],
+                    },
+                    "cfg_set": {
+                        "description": "Items enabled for `#[cfg(...)]`, either `name` or `[key, value]`.",
+                        "type": ["array", "null"],
+                        "items": {
+                            "oneOf": [
+                                { "type": "string" },
+                                {
+                                    "type": "array",
+                                    "prefixItems": [{ "type": "string" }, { "type": "string" }],
+                                    "minItems": 2,
+                                    "maxItems": 2,
+                                },
+                            ],
+                        },
+                    },
+                    "dependencies": {
+                        "description": "Dependencies of the crate, keyed by the name they are referred to by.",
+                        "type": "object",
+                        "additionalProperties": { "$ref": "#/$defs/DependencySettings" },
+                    },
+                    "experimental_features": {
+                        "description": "Experimental compiler features enabled for the crate.",
+                        "type": "object",
+                        "properties": experimental_features,
+                        "additionalProperties": false,
+                    },
+                },
+            },
+            "DependencySettings": {
+                "description": "Settings of a single dependency.",
+                "type": "object",
+                "properties": {
+                    "discriminator": {
+                        "description": "Identifies different copies of the same dependency.",
+                        "type": ["string", "null"],
+                    },
+                },
+            },
+        },
+    })
+}
@@ -0,0 +1,1415 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::json;
+
+#[path = "common/fixture.rs"]
+mod fixture;
+
+use fixture::Workspace;
+
+/// A fresh directory for a single test, unique across tests running in parallel.
+fn temp_dir() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "scarb-eject-test-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// An executable in `dir` standing in for `scarb metadata`, which runs `script` and prints
+/// metadata of `workspace`.
+#[cfg(unix)]
+fn fake_scarb(dir: &Path, workspace: &Workspace, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scarb = dir.join("scarb");
+    let metadata = workspace.metadata();
+    fs::write(&scarb, format!("#!/bin/sh\n{script}\necho '{metadata}'\n")).unwrap();
+    fs::set_permissions(&scarb, fs::Permissions::from_mode(0o755)).unwrap();
+    scarb
+}
+
+/// `scarb-eject` reading metadata of `workspace`, with `args`.
+fn command(workspace: &Workspace, args: &[&str]) -> Command {
+    let metadata = temp_dir().join("metadata.json");
+    fs::write(&metadata, workspace.metadata().to_string()).unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_scarb-eject"));
+    command
+        .arg("--metadata-file")
+        .arg(&metadata)
+        .args(args)
+        .env_remove("RUST_LOG")
+        .env_remove("SCARB_PACKAGES_FILTER")
+        .env("RUST_BACKTRACE", "0");
+    command
+}
+
+fn run(workspace: &Workspace, args: &[&str]) -> Output {
+    command(workspace, args).output().unwrap()
+}
+
+/// Standard output of `output`, which must be successful.
+fn stdout(output: Output) -> String {
+    assert!(
+        output.status.success(),
+        "scarb-eject failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Standard output of a successful run for the package `a`.
+fn eject(workspace: &Workspace, args: &[&str]) -> String {
+    stdout(run(workspace, &[&["--package", "a"], args].concat()))
+}
+
+/// Settings of the crate `name` as resolved for the package `a`.
+fn inspect(workspace: &Workspace, name: &str, args: &[&str]) -> serde_json::Value {
+    let args = [&["--inspect-crate", name, "--format", "json"], args].concat();
+    serde_json::from_str(&eject(workspace, &args)).unwrap()
+}
+
+#[test]
+fn strip_empty_sections_keeps_dependencies_on_crates_named_like_tables() {
+    let mut workspace = Workspace::default();
+    workspace.package("a", &["config", "dependencies"]);
+    workspace.package("config", &[]);
+    workspace.package("dependencies", &[]);
+    workspace.unit(
+        "a",
+        "lib",
+        &[
+            ("a", &["config", "dependencies"]),
+            ("config", &[]),
+            ("dependencies", &[]),
+        ],
+    );
+
+    let args = ["-o", "-", "--no-header"];
+    assert_eq!(
+        eject(&workspace, &args).trim_end(),
+        r#"
+[crate_roots]
+a = "/ws/a/src"
+config = "/ws/config/src"
+dependencies = "/ws/dependencies/src"
+
+[config.global]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+version = "0.1.0"
+
+[config.global.dependencies.a]
+
+[config.global.dependencies.config]
+
+[config.global.dependencies.dependencies]
+
+[config.global.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+
+[config.override.a]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+name = "a"
+version = "0.1.0"
+
+[config.override.a.dependencies.config]
+
+[config.override.a.dependencies.dependencies]
+
+[config.override.a.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+
+[config.override.config]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+name = "config"
+version = "0.1.0"
+
+[config.override.config.dependencies]
+
+[config.override.config.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+
+[config.override.dependencies]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+name = "dependencies"
+version = "0.1.0"
+
+[config.override.dependencies.dependencies]
+
+[config.override.dependencies.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+"#
+        .trim()
+    );
+    assert_eq!(
+        eject(
+            &workspace,
+            &[&args[..], &["--strip-empty-sections"]].concat()
+        )
+        .trim_end(),
+        r#"
+[crate_roots]
+a = "/ws/a/src"
+config = "/ws/config/src"
+dependencies = "/ws/dependencies/src"
+
+[config.global]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+version = "0.1.0"
+
+[config.global.dependencies.a]
+
+[config.global.dependencies.config]
+
+[config.global.dependencies.dependencies]
+
+[config.global.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+
+[config.override.a]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+name = "a"
+version = "0.1.0"
+
+[config.override.a.dependencies.config]
+
+[config.override.a.dependencies.dependencies]
+
+[config.override.a.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+
+[config.override.config]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+name = "config"
+version = "0.1.0"
+
+[config.override.config.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+
+[config.override.dependencies]
+cfg_set = [["target", "lib"]]
+edition = "2024_07"
+name = "dependencies"
+version = "0.1.0"
+
+[config.override.dependencies.experimental_features]
+associated_item_constraints = false
+coupons = false
+negative_impls = false
+"#
+        .trim()
+    );
+}
+
+#[test]
+fn append_cfg_from_env_reaches_main_crate() {
+    let workspace = Workspace::chain();
+    let output = command(
+        &workspace,
+        &[
+            "--package",
+            "a",
+            "--inspect-crate",
+            "a",
+            "--format",
+            "json",
+            "--append-cfg-from-env",
+            "SCARB_EJECT_TEST_CFG",
+        ],
+    )
+    .env("SCARB_EJECT_TEST_CFG", "feature=foo, extra")
+    .output()
+    .unwrap();
+    let settings: serde_json::Value = serde_json::from_str(&stdout(output)).unwrap();
+    assert_eq!(
+        settings["cfg_set"],
+        serde_json::json!(["extra", ["feature", "foo"], ["target", "lib"]])
+    );
+
+    assert_eq!(
+        inspect(&workspace, "a", &[])["cfg_set"],
+        serde_json::json!([["target", "lib"]])
+    );
+}
+
+#[test]
+fn packages_glob_selects_matching_members() {
+    let mut workspace = Workspace::default();
+    for name in ["a", "b1", "b2"] {
+        workspace.package(name, &[]);
+        workspace.unit(name, "lib", &[(name, &[])]);
+    }
+
+    let output = stdout(run(&workspace, &["--packages-glob", "b*", "-o", "-"]));
+    assert!(!output.contains("# package: a\n"));
+    assert!(output.contains("# package: b1\n"));
+    assert!(output.contains("# package: b2\n"));
+
+    let output = stdout(run(
+        &workspace,
+        &["--packages-glob", "*/Scarb.toml", "-o", "-"],
+    ));
+    assert_eq!(output.matches("# package: ").count(), 3);
+
+    assert!(!run(&workspace, &["--packages-glob", "c*", "-o", "-"])
+        .status
+        .success());
+    assert!(!run(&workspace, &["--packages-glob", "b*", "--print-path"])
+        .status
+        .success());
+}
+
+#[test]
+fn config_file_defaults_are_overridden_by_command_line() {
+    let workspace = Workspace::chain();
+    let dir = temp_dir();
+    fs::write(
+        dir.join(".scarb-eject.toml"),
+        "format = \"json\"\nno-version = true\nposix_paths = false\nverbose = 2\n",
+    )
+    .unwrap();
+    let eject_in_dir = |args: &[&str]| {
+        let args = [&["--package", "a", "-o", "-"], args].concat();
+        stdout(
+            command(&workspace, &args)
+                .current_dir(&dir)
+                .output()
+                .unwrap(),
+        )
+    };
+
+    let json: serde_json::Value = serde_json::from_str(&eject_in_dir(&[])).unwrap();
+    assert!(json["config"]["global"]["version"].is_null());
+
+    let toml = eject_in_dir(&["--format", "toml"]);
+    assert!(toml.contains("[crate_roots]\n"));
+    assert!(!toml.contains("version = "));
+
+    fs::write(
+        dir.join(".scarb-eject.toml"),
+        "include_corelib = true\noutput = \"out.toml\"\n",
+    )
+    .unwrap();
+    let fragment = |args: &[&str]| {
+        let args = [&["--package", "a", "--fragment", "--roots-only"], args].concat();
+        stdout(
+            command(&workspace, &args)
+                .current_dir(&dir)
+                .output()
+                .unwrap(),
+        )
+    };
+    assert!(fragment(&[]).contains("core = \"/corelib/src\""));
+    assert!(!fragment(&["--include-corelib=false"]).contains("core = "));
+    assert!(!dir.join("out.toml").exists());
+
+    fs::write(dir.join(".scarb-eject.toml"), "no-such-option = 1\n").unwrap();
+    let output = command(&workspace, &["--package", "a"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown field `no_such_option`"));
+}
+
+#[test]
+fn all_to_stdout_as_json_is_a_single_document() {
+    let mut workspace = Workspace::default();
+    for name in ["a", "b"] {
+        workspace.package(name, &[]);
+        workspace.unit(name, "lib", &[(name, &[])]);
+    }
+
+    let output = stdout(run(&workspace, &["--all", "-o", "-", "--format", "json"]));
+    let documents: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&output).unwrap();
+    assert_eq!(documents.keys().collect::<Vec<_>>(), ["a", "b"]);
+    assert_eq!(documents["b"]["crate_roots"]["b"], "/ws/b/src");
+
+    let output = stdout(run(&workspace, &["--all", "-o", "-"]));
+    assert!(output.starts_with("# package: a\n"));
+    assert!(output.contains("# package: b\n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn frozen_restores_lockfile_updated_by_scarb() {
+    let dir = temp_dir();
+    let workspace = Workspace::at(&dir);
+    let lockfile = dir.join("Scarb.lock");
+    // Update the lockfile like Scarb would after a manifest change.
+    let scarb = fake_scarb(&dir, &workspace, "echo updated > Scarb.lock");
+
+    for original in [Some("original\n"), None] {
+        match original {
+            Some(original) => fs::write(&lockfile, original).unwrap(),
+            None => fs::remove_file(&lockfile).unwrap(),
+        }
+        let output = Command::new(env!("CARGO_BIN_EXE_scarb-eject"))
+            .args(["--frozen", "-o", "-"])
+            .current_dir(&dir)
+            .env("SCARB", &scarb)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("`--frozen` was passed"));
+        assert_eq!(fs::read_to_string(&lockfile).ok().as_deref(), original);
+    }
+}
+
+#[test]
+fn discriminators_are_logged_at_info_level() {
+    let workspace = Workspace::chain();
+    let stderr = |args: &[&str]| {
+        let args = [&["--package", "a", "-o", "-"], args].concat();
+        String::from_utf8(run(&workspace, &args).stderr).unwrap()
+    };
+
+    assert!(!stderr(&[]).contains("discriminator"));
+    assert!(stderr(&["-v"]).contains("a: discriminator none"));
+    assert!(stderr(&["--log-level", "info"]).contains("b: discriminator none"));
+    assert!(!stderr(&["-v", "--log-level", "warn"]).contains("discriminator"));
+}
+
+#[test]
+fn unknown_experimental_feature_is_a_warning() {
+    let mut workspace = Workspace::chain();
+    workspace.packages[0]["experimental_features"] = json!(["negative_impls", "no_such_feature"]);
+
+    let output = run(&workspace, &["--package", "a", "-o", "-"]);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(stderr.contains("unknown experimental feature `no_such_feature` in package: a"));
+    assert!(stdout(output).contains("negative_impls = true"));
+}
+
+#[test]
+fn unmatched_package_lists_available_ones() {
+    let output = run(&Workspace::chain(), &["--package", "typo", "-o", "-"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("failed to select package to eject, available packages: a, b, c"));
+}
+
+#[test]
+fn dry_run_does_not_create_output() {
+    let dir = temp_dir();
+    let output = dir.join("cairo_project.toml");
+    let output = output.to_str().unwrap();
+
+    let result = run(
+        &Workspace::chain(),
+        &["--package", "a", "-o", output, "--dry-run"],
+    );
+    assert!(result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("would write ") && stderr.contains(&format!(" bytes to {output}")));
+    assert!(!dir.join("cairo_project.toml").exists());
+
+    let result = run(
+        &Workspace::chain(),
+        &["--package", "a", "-o", output, "--dry-run", "--diff"],
+    );
+    assert!(String::from_utf8_lossy(&result.stdout).contains("+[crate_roots]"));
+    assert!(!dir.join("cairo_project.toml").exists());
+
+    let result = eject(&Workspace::chain(), &["-o", "-", "--dry-run"]);
+    assert!(result.contains("[crate_roots]"));
+}
+
+#[test]
+fn dangling_dependency_id_is_skipped_with_a_warning() {
+    let mut workspace = Workspace::default();
+    workspace.package("a", &["b"]);
+    workspace.package("b", &[]);
+    workspace.unit("a", "lib", &[("a", &["b", "missing"]), ("b", &[])]);
+
+    let output = run(&workspace, &["--package", "a", "-o", "-"]);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("dependency missing of crate a does not match any component, skipping it"));
+    let toml = stdout(output);
+    assert!(toml.contains("[config.override.a.dependencies.b]"));
+    assert!(!toml.contains("missing"));
+}
+
+#[test]
+fn features_override_enables_disables_and_warns_about_unknown_names() {
+    let mut workspace = Workspace::chain();
+    workspace.packages[0]["experimental_features"] = json!(["negative_impls"]);
+    let features = temp_dir().join("features.toml");
+    fs::write(
+        &features,
+        "coupons = true\nnegative_impls = false\nno_such_feature = true\n",
+    )
+    .unwrap();
+
+    let args = [
+        "--package",
+        "a",
+        "--inspect-crate",
+        "a",
+        "--format",
+        "json",
+        "--features-override",
+        features.to_str().unwrap(),
+    ];
+    let output = run(&workspace, &args);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("unknown experimental feature `no_such_feature` in features override"));
+    let settings: serde_json::Value = serde_json::from_str(&stdout(output)).unwrap();
+    assert_eq!(
+        settings["experimental_features"],
+        json!({
+            "associated_item_constraints": false,
+            "coupons": true,
+            "negative_impls": false,
+        })
+    );
+}
+
+#[test]
+fn roots_only_emits_just_crate_roots() {
+    let toml = eject(
+        &Workspace::chain(),
+        &["-o", "-", "--no-header", "--roots-only"],
+    );
+    assert_eq!(
+        toml.trim_end(),
+        "[crate_roots]\na = \"/ws/a/src\"\nb = \"/ws/b/src\"\nc = \"/ws/c/src\""
+    );
+}
+
+#[test]
+fn warnings_reach_stderr() {
+    let mut workspace = Workspace::chain();
+    workspace.packages[1]["edition"] = json!("2099_99");
+
+    let output = run(&workspace, &["--package", "a", "-o", "-"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("WARN"));
+    assert!(stderr.contains("failed to parse edition of package: b"));
+
+    let output = run(
+        &workspace,
+        &["--package", "a", "-o", "-", "--log-level", "error"],
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+#[cfg(unix)]
+fn metadata_cache_is_reused_until_manifests_change() {
+    let dir = temp_dir();
+    let mut workspace = Workspace::at(&dir);
+    workspace.package("a", &[]);
+    workspace.unit("a", "lib", &[("a", &[])]);
+    let calls = dir.join("calls");
+    let scarb = fake_scarb(&dir, &workspace, &format!("echo >> {}", calls.display()));
+    let cache = dir.join("metadata-cache.json");
+
+    let eject_cached = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_scarb-eject"))
+            .arg("--metadata-cache")
+            .arg(&cache)
+            .args(["-o", "-"])
+            .current_dir(&dir)
+            .env("SCARB", &scarb)
+            .output()
+            .unwrap();
+        let toml = stdout(output);
+        assert!(toml.contains("a = "));
+        fs::read_to_string(&calls).unwrap().lines().count()
+    };
+
+    assert_eq!(eject_cached(), 1);
+    assert!(cache.is_file());
+    assert_eq!(eject_cached(), 1);
+
+    fs::write(dir.join("Scarb.toml"), "[workspace]\n").unwrap();
+    assert_eq!(eject_cached(), 2);
+    assert_eq!(eject_cached(), 2);
+
+    fs::write(dir.join("Scarb.lock"), "version = 1\n").unwrap();
+    assert_eq!(eject_cached(), 3);
+}
+
+#[test]
+fn only_a_bare_dash_means_stdout() {
+    let workspace = Workspace::chain();
+    let dir = temp_dir();
+    let eject_in_dir = |output: &str| {
+        let args = ["--package", "a", "-o", output];
+        stdout(
+            command(&workspace, &args)
+                .current_dir(&dir)
+                .output()
+                .unwrap(),
+        )
+    };
+
+    assert!(eject_in_dir("-").contains("[crate_roots]"));
+    assert!(!dir.join("-").exists());
+
+    assert!(eject_in_dir("./-").is_empty());
+    assert!(fs::read_to_string(dir.join("-"))
+        .unwrap()
+        .contains("[crate_roots]"));
+
+    assert!(eject_in_dir("out.toml").is_empty());
+    assert!(fs::read_to_string(dir.join("out.toml"))
+        .unwrap()
+        .contains("[crate_roots]"));
+}
+
+#[test]
+fn merge_targets_unions_units_with_the_latter_winning() {
+    let mut workspace = Workspace::default();
+    workspace.package("a", &["b", "t"]);
+    workspace.package("b", &[]);
+    workspace.package("t", &[]);
+    workspace.unit("a", "lib", &[("a", &["b"]), ("b", &[])]);
+    workspace.unit("a", "test", &[("a", &["b", "t"]), ("b", &[]), ("t", &[])]);
+
+    let output = run(
+        &workspace,
+        &[
+            "--package",
+            "a",
+            "-o",
+            "-",
+            "--format",
+            "json",
+            "--merge-targets",
+            "lib,test",
+        ],
+    );
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("conflicting settings for crate `a`, using the latter"));
+    let config: serde_json::Value = serde_json::from_str(&stdout(output)).unwrap();
+    assert_eq!(
+        config["crate_roots"],
+        json!({ "a": "/ws/a/src", "b": "/ws/b/src", "t": "/ws/t/src" })
+    );
+    assert_eq!(
+        config["config"]["override"]["a"]["cfg_set"],
+        json!([["target", "test"]])
+    );
+}
+
+#[test]
+fn preserve_comments_keeps_leading_banner() {
+    let dir = temp_dir();
+    let output = dir.join("cairo_project.toml");
+    fs::write(
+        &output,
+        "# Hand-written banner.\n# Keep me.\n\n[crate_roots]\n# not me\na = \"/old\"\n",
+    )
+    .unwrap();
+
+    let args = ["-o", output.to_str().unwrap(), "--preserve-comments"];
+    for _ in 0..2 {
+        eject(&Workspace::chain(), &args);
+        let content = fs::read_to_string(&output).unwrap();
+        assert!(content.starts_with(
+            "# Hand-written banner.\n# Keep me.\n# generated by scarb-eject from target: lib\n"
+        ));
+        assert_eq!(content.matches("# Keep me.").count(), 1);
+        assert!(!content.contains("# not me"));
+    }
+}
+
+#[test]
+fn package_version_must_be_semver() {
+    let toml = eject(
+        &Workspace::chain(),
+        &["-o", "-", "--package-version", "1.2.3"],
+    );
+    assert!(toml.contains("name = \"a\"\nversion = \"1.2.3\""));
+    let output = run(
+        &Workspace::chain(),
+        &["--package", "a", "-o", "-", "--package-version", "1.2"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid value '1.2'"));
+}
+
+#[test]
+fn external_roots_are_warned_about_or_denied() {
+    let mut workspace = Workspace::chain();
+    workspace.units[0]["components_data"][2]["source_path"] = json!("/elsewhere/c/src/lib.cairo");
+
+    let output = run(&workspace, &["--package", "a", "-o", "-"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("outside of workspace"));
+
+    let output = run(
+        &workspace,
+        &["--package", "a", "-o", "-", "--warn-external-roots"],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("crate root of c is outside of workspace /ws: /elsewhere/c/src"));
+
+    let output = run(
+        &workspace,
+        &["--package", "a", "-o", "-", "--deny-external-roots"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("crates with roots outside of workspace /ws: c"));
+}
+
+#[test]
+fn fragment_is_just_the_tables() {
+    let fragment = eject(&Workspace::chain(), &["--fragment"]);
+    assert!(fragment.starts_with("[crate_roots]\n"));
+    assert_eq!(fragment, format!("{}\n", fragment.trim()));
+    assert_eq!(
+        fragment.trim(),
+        eject(&Workspace::chain(), &["-o", "-", "--no-header"]).trim()
+    );
+}
+
+#[test]
+fn main_manifest_picks_one_of_same_named_packages() {
+    let mut workspace = Workspace::default();
+    workspace.package("a", &[]);
+    workspace.unit("a", "lib", &[("a", &[])]);
+    let nested = "a 0.1.0 (path+file:///ws/nested/a)";
+    let package = workspace.package("a", &[]);
+    package["id"] = json!(nested);
+    package["manifest_path"] = json!("/ws/nested/a/Scarb.toml");
+    package["root"] = json!("/ws/nested/a");
+    let unit = workspace.unit("a", "lib", &[("a", &[])]);
+    unit["id"] = json!("nested-a-lib");
+    unit["package"] = json!(nested);
+    unit["components_data"][0]["package"] = json!(nested);
+    unit["components_data"][0]["source_path"] = json!("/ws/nested/a/src/lib.cairo");
+
+    let roots = |manifest: &str| {
+        let args = ["--main-manifest", manifest, "-o", "-", "--roots-only"];
+        stdout(run(&workspace, &args))
+    };
+    assert!(roots("/ws/a/Scarb.toml").contains("a = \"/ws/a/src\""));
+    assert!(roots("/ws/nested/a/Scarb.toml").contains("a = \"/ws/nested/a/src\""));
+
+    let output = run(
+        &workspace,
+        &["--main-manifest", "/ws/b/Scarb.toml", "-o", "-"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("no workspace member with manifest /ws/b/Scarb.toml"));
+}
+
+#[test]
+fn toml_layout_is_canonical() {
+    let mut workspace = Workspace::default();
+    workspace.package("z", &[]);
+    workspace.package("a", &["z", "m"]);
+    workspace.package("m", &[]);
+    workspace.unit("a", "lib", &[("z", &[]), ("a", &["z", "m"]), ("m", &[])]);
+
+    let toml = eject(&workspace, &["-o", "-", "--no-header"]);
+    assert_eq!(
+        toml.trim_end(),
+        include_str!("golden/canonical_layout.toml").trim_end()
+    );
+}
+
+#[test]
+fn missing_package_of_component_is_a_warning_or_strict_error() {
+    let mut workspace = Workspace::chain();
+    workspace.units[0]["components_data"][2]["package"] = json!("gone 0.1.0 (path+file:///gone)");
+    let message = "package gone 0.1.0 (path+file:///gone) of component c is missing from metadata";
+
+    let output = run(&workspace, &["--package", "a", "-o", "-"]);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(stdout(output).contains("[config.override.c]"));
+    assert!(stderr.contains(message));
+
+    let output = run(&workspace, &["--package", "a", "-o", "-", "--strict"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(message));
+}
+
+#[test]
+#[cfg(unix)]
+fn canonicalize_resolves_symlinked_crate_dirs() {
+    let dir = fs::canonicalize(temp_dir()).unwrap();
+    for name in ["a", "b", "real-c"] {
+        fs::create_dir_all(dir.join(name).join("src")).unwrap();
+    }
+    std::os::unix::fs::symlink(dir.join("real-c"), dir.join("c")).unwrap();
+    let workspace = Workspace::chain_at(&dir);
+
+    let root = |toml: &str, name: &str| {
+        let crate_roots: toml::Table = toml::from_str(toml).unwrap();
+        crate_roots["crate_roots"][name]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    };
+    let toml = eject(&workspace, &["-o", "-", "--roots-only"]);
+    assert_eq!(root(&toml, "c"), dir.join("c/src").display().to_string());
+
+    let toml = eject(&workspace, &["-o", "-", "--roots-only", "--canonicalize"]);
+    assert_eq!(root(&toml, "a"), dir.join("a/src").display().to_string());
+    assert_eq!(
+        root(&toml, "c"),
+        dir.join("real-c/src").display().to_string()
+    );
+}
+
+#[test]
+fn no_version_leaves_out_every_version_key() {
+    let toml = eject(&Workspace::chain(), &["-o", "-", "--no-header"]);
+    assert!(toml.contains("version = \"0.1.0\""));
+
+    let toml = eject(
+        &Workspace::chain(),
+        &["-o", "-", "--no-header", "--no-version"],
+    );
+    assert!(toml.contains("[config.override.a]"));
+    assert!(!toml.contains("version"));
+}
+
+#[test]
+fn deny_root_conflicts_lists_both_roots() {
+    let mut workspace = Workspace::chain();
+    let components = workspace.units[0]["components_data"]
+        .as_array_mut()
+        .unwrap();
+    let mut other = components[2].clone();
+    other["id"] = json!("other-c");
+    other["source_path"] = json!("/ws/other/c/src/lib.cairo");
+    components.push(other);
+
+    assert!(run(&workspace, &["--package", "a", "-o", "-"])
+        .status
+        .success());
+
+    let output = run(
+        &workspace,
+        &["--package", "a", "-o", "-", "--deny-root-conflicts"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("crate name `c` maps to two different roots: /ws/c/src and /ws/other/c/src"));
+}
+
+#[test]
+fn relative_to_workspace_output_or_dir() {
+    let dir = temp_dir();
+    let mut workspace = Workspace::chain_at(&dir);
+    // Like a dependency from the Scarb cache.
+    workspace.units[0]["components_data"][2]["source_path"] = json!("/elsewhere/c/src/lib.cairo");
+    let roots = |toml: &str| {
+        let toml: toml::Table = toml::from_str(toml).unwrap();
+        ["a", "b", "c", "core"].map(|name| toml["crate_roots"][name].as_str().unwrap().to_owned())
+    };
+    let args = ["--roots-only", "--include-corelib", "--relative-to"];
+
+    let output = run(
+        &workspace,
+        &[&["--package", "a", "-o", "-"], &args[..], &["workspace"]].concat(),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert_eq!(
+        roots(&stdout(output)),
+        ["a/src", "b/src", "/elsewhere/c/src", "/corelib/src"]
+    );
+    assert!(stderr.contains("keeping corelib crate root absolute"));
+    assert!(stderr.contains(&format!(
+        "crate root of c is outside of {} and of the workspace, keeping it absolute",
+        dir.display()
+    )));
+
+    fs::create_dir(dir.join("out")).unwrap();
+    let output = dir.join("out").join("cairo_project.toml");
+    eject(
+        &workspace,
+        &[&["-o", output.to_str().unwrap()], &args[..], &["output"]].concat(),
+    );
+    assert_eq!(
+        roots(&fs::read_to_string(&output).unwrap()),
+        ["../a/src", "../b/src", "/elsewhere/c/src", "/corelib/src"]
+    );
+
+    let base = dir.join("b");
+    let toml = eject(
+        &workspace,
+        &[&["-o", "-"], &args[..], &[base.to_str().unwrap()]].concat(),
+    );
+    assert_eq!(
+        roots(&toml),
+        ["../a/src", "src", "/elsewhere/c/src", "/corelib/src"]
+    );
+}
+
+#[test]
+fn dependency_order_does_not_change_output() {
+    let eject_shuffled = |reversed: bool| {
+        let mut dependencies = ["b", "c", "d"];
+        let mut components: Vec<(&str, &[&str])> = vec![("b", &[]), ("c", &[]), ("d", &[])];
+        if reversed {
+            dependencies.reverse();
+            components.reverse();
+        }
+        components.insert(if reversed { 3 } else { 0 }, ("a", &dependencies));
+
+        let mut workspace = Workspace::default();
+        workspace.package("a", &dependencies);
+        for name in ["b", "c", "d"] {
+            workspace.package(name, &[]);
+        }
+        workspace.unit("a", "lib", &components);
+        eject(&workspace, &["-o", "-", "--no-header"])
+    };
+
+    let toml = eject_shuffled(false);
+    assert!(toml.contains(
+        "[config.override.a.dependencies.b]\n\n\
+         [config.override.a.dependencies.c]\n\n\
+         [config.override.a.dependencies.d]\n"
+    ));
+    assert_eq!(toml, eject_shuffled(true));
+}
+
+#[test]
+fn metadata_file_of_another_format_version_is_rejected() {
+    let error = |version: Option<serde_json::Value>| {
+        let dir = temp_dir();
+        let mut metadata = Workspace::chain().metadata();
+        match version {
+            Some(version) => metadata["version"] = version,
+            None => {
+                metadata.as_object_mut().unwrap().remove("version");
+            }
+        }
+        let file = dir.join("metadata.json");
+        fs::write(&file, metadata.to_string()).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_scarb-eject"))
+            .args(["--package", "a", "-o", "-", "--metadata-file"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let stderr = error(Some(json!(2)));
+    assert!(stderr.contains("metadata format version 2 is not supported, expected 1"));
+    assert!(stderr.contains("help: regenerate it with `scarb metadata --format-version 1`"));
+    assert!(error(None).contains("metadata has no format version"));
+}
+
+#[test]
+fn crate_editions_are_overridden_independently() {
+    let workspace = Workspace::chain();
+    let args = [
+        "--crate-edition",
+        "b=2023_10",
+        "--crate-edition",
+        "c=2023_01",
+        "--crate-edition",
+        "nope=2023_10",
+    ];
+    let editions = ["a", "b", "c"].map(|name| inspect(&workspace, name, &args)["edition"].clone());
+    assert_eq!(
+        editions,
+        [json!("2024_07"), json!("2023_10"), json!("2023_01")]
+    );
+
+    let output = run(
+        &workspace,
+        &[&["--package", "a", "-o", "-"], &args[..]].concat(),
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "crate `nope` with an edition override is not a component of compilation unit a-lib"
+    ));
+
+    let output = run(
+        &workspace,
+        &["--package", "a", "-o", "-", "--crate-edition", "b=2099_99"],
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn line_endings_are_applied_and_ignored_by_check() {
+    let workspace = Workspace::chain();
+    let output = temp_dir().join("cairo_project.toml");
+    let output = output.to_str().unwrap();
+    let written = |line_ending: &str| {
+        eject(&workspace, &["-o", output, "--line-ending", line_ending]);
+        fs::read_to_string(output).unwrap()
+    };
+
+    let lf = written("lf");
+    assert!(lf.ends_with('\n') && !lf.contains('\r'));
+    let crlf = written("crlf");
+    assert!(crlf.ends_with("\r\n"));
+    assert_eq!(crlf.replace("\r\n", "\n"), lf);
+    let native = written("native");
+    assert_eq!(native, if cfg!(windows) { &crlf } else { &lf }.as_str());
+
+    fs::write(output, &crlf).unwrap();
+    eject(&workspace, &["-o", output, "--check"]);
+    fs::write(output, &lf).unwrap();
+    eject(
+        &workspace,
+        &["-o", output, "--check", "--line-ending", "crlf"],
+    );
+}
+
+#[test]
+fn test_target_includes_test_only_crates() {
+    let mut workspace = Workspace::default();
+    workspace.package("a", &["b", "t"])["dependencies"][1]["kind"] = json!("dev");
+    workspace.package("b", &[]);
+    workspace.package("t", &[]);
+    workspace.unit("a", "lib", &[("a", &["b"]), ("b", &[])]);
+    workspace.unit("a", "test", &[("a", &["b", "t"]), ("b", &[]), ("t", &[])]);
+
+    let lib = eject(&workspace, &["-o", "-", "--roots-only"]);
+    assert!(!lib.contains("t = "));
+    let test = eject(
+        &workspace,
+        &["-o", "-", "--roots-only", "--no-header", "--target", "test"],
+    );
+    assert_eq!(
+        test.trim_end(),
+        "[crate_roots]\na = \"/ws/a/src\"\nb = \"/ws/b/src\"\nt = \"/ws/t/src\""
+    );
+    let settings = inspect(&workspace, "a", &["--target", "test"]);
+    let dependencies = settings["dependencies"].as_object().unwrap();
+    assert_eq!(dependencies.keys().collect::<Vec<_>>(), ["b", "t"]);
+}
+
+#[test]
+fn print_path_shows_default_or_explicit_output() {
+    let workspace = Workspace::chain();
+    assert_eq!(
+        stdout(run(&workspace, &["--print-path"])),
+        "/ws/cairo_project.toml\n"
+    );
+    assert_eq!(
+        stdout(run(&workspace, &["--print-path", "--format", "json"])),
+        "/ws/cairo_project.json\n"
+    );
+
+    // An explicit output is printed without reading metadata at all.
+    let explicit = |output: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_scarb-eject"))
+            .args([
+                "--print-path",
+                "--metadata-file",
+                "/no/such/metadata.json",
+                "-o",
+                output,
+            ])
+            .output()
+            .unwrap();
+        stdout(output)
+    };
+    assert_eq!(explicit("out/config.toml"), "out/config.toml\n");
+    assert_eq!(explicit("-"), "-\n");
+}
+
+#[test]
+fn check_verifies_lockfile_hash() {
+    let dir = temp_dir();
+    let workspace = Workspace::chain_at(&dir);
+    fs::write(dir.join("Scarb.lock"), "version = 1\n").unwrap();
+    let output = dir.join("cairo_project.toml");
+    let args = ["-o", output.to_str().unwrap(), "--with-lockfile-hash"];
+
+    eject(&workspace, &args);
+    let toml = fs::read_to_string(&output).unwrap();
+    let stamp = toml
+        .lines()
+        .find_map(|line| line.strip_prefix("# scarb-lock: "))
+        .unwrap();
+    assert_eq!(stamp.len(), 16);
+    eject(&workspace, &[&args[..], &["--check"]].concat());
+
+    // Line endings alone do not count as a change.
+    fs::write(dir.join("Scarb.lock"), "version = 1\r\n").unwrap();
+    eject(&workspace, &[&args[..], &["--check"]].concat());
+
+    fs::write(dir.join("Scarb.lock"), "version = 2\n").unwrap();
+    let result = run(
+        &workspace,
+        &[&["--package", "a"], &args[..], &["--check"]].concat(),
+    );
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains(&format!(
+        "{} was generated for a different Scarb.lock",
+        output.display()
+    )));
+}
+
+#[test]
+fn output_encoding_controls_bom_and_check_ignores_it() {
+    let workspace = Workspace::chain();
+    let output = temp_dir().join("cairo_project.toml");
+    let output = output.to_str().unwrap();
+
+    eject(&workspace, &["-o", output]);
+    let plain = fs::read(output).unwrap();
+    assert!(plain.starts_with(b"# generated by scarb-eject"));
+    eject(&workspace, &["-o", output, "--output-encoding", "utf8"]);
+    assert_eq!(fs::read(output).unwrap(), plain);
+
+    eject(&workspace, &["-o", output, "--output-encoding", "utf8-bom"]);
+    let bom = fs::read(output).unwrap();
+    assert_eq!(bom, [&b"\xef\xbb\xbf"[..], &plain].concat());
+
+    eject(&workspace, &["-o", output, "--check"]);
+    fs::write(output, &plain).unwrap();
+    eject(
+        &workspace,
+        &["-o", output, "--check", "--output-encoding", "utf8-bom"],
+    );
+}
+
+#[test]
+fn crate_no_features_disables_features_of_named_crates_only() {
+    let mut workspace = Workspace::chain();
+    for package in &mut workspace.packages {
+        package["experimental_features"] = json!(["negative_impls"]);
+    }
+    let args = ["--crate-no-features", "b", "--crate-no-features", "nope"];
+    let negative_impls = ["a", "b", "c"].map(|name| {
+        inspect(&workspace, name, &args)["experimental_features"]["negative_impls"].clone()
+    });
+    assert_eq!(negative_impls, [json!(true), json!(false), json!(true)]);
+
+    let output = run(
+        &workspace,
+        &[&["--package", "a", "-o", "-"], &args[..]].concat(),
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "crate `nope` with disabled features is not a component of compilation unit a-lib"
+    ));
+}
+
+#[test]
+fn detect_cycles_warns_about_a_cycle() {
+    let mut workspace = Workspace::default();
+    workspace.package("a", &["b"]);
+    workspace.package("b", &["a"]);
+    workspace.unit("a", "lib", &[("a", &["b"]), ("b", &["a"])]);
+
+    let output = run(
+        &workspace,
+        &["--package", "a", "-o", "-", "--detect-cycles"],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    stdout(output);
+    assert!(stderr.contains("dependency cycle between crates: a -> b -> a"));
+}
+
+#[test]
+fn annotate_comments_crate_roots_with_their_packages() {
+    let workspace = Workspace::chain();
+    let args = ["--roots-only", "--include-corelib", "--annotate"];
+    let toml = eject(
+        &workspace,
+        &[&["-o", "-", "--no-header"], &args[..]].concat(),
+    );
+    assert_eq!(
+        toml.trim_end(),
+        include_str!("golden/annotate.toml").trim_end()
+    );
+
+    let json = eject(
+        &workspace,
+        &[&["-o", "-", "--format", "json"], &args[..]].concat(),
+    );
+    assert!(!json.contains("from:"));
+
+    let output = temp_dir().join("cairo_project.toml");
+    let output = output.to_str().unwrap();
+    eject(
+        &workspace,
+        &["-o", output, "--roots-only", "--include-corelib"],
+    );
+    eject(
+        &workspace,
+        &[&["-o", output, "--check"], &args[..]].concat(),
+    );
+}
+
+#[test]
+fn git_add_stages_the_output_inside_a_repository() {
+    let git = |dir: &Path, args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let dir = temp_dir();
+    stdout(git(&dir, &["init", "-q"]));
+    let output = dir.join("cairo_project.toml");
+
+    eject(
+        &Workspace::chain(),
+        &["-o", output.to_str().unwrap(), "--git-add"],
+    );
+    assert_eq!(
+        stdout(git(&dir, &["diff", "--cached", "--name-only"])),
+        "cairo_project.toml\n"
+    );
+
+    // Skipped if the temporary directory itself happens to be in a repository.
+    let outside = temp_dir();
+    if git(&outside, &["rev-parse"]).status.success() {
+        return;
+    }
+    let output = outside.join("cairo_project.toml");
+    let result = run(
+        &Workspace::chain(),
+        &[
+            "--package",
+            "a",
+            "-o",
+            output.to_str().unwrap(),
+            "--git-add",
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&result.stderr).into_owned();
+    stdout(result);
+    assert!(output.is_file());
+    assert!(stderr.contains("is not in a git repository, not staging it"));
+}
+
+#[test]
+fn no_crate_depends_on_corelib_explicitly() {
+    for include_corelib in [false, true] {
+        let mut args = vec!["-o", "-", "--format", "json"];
+        if include_corelib {
+            args.push("--include-corelib");
+        }
+        let config: serde_json::Value =
+            serde_json::from_str(&eject(&Workspace::chain(), &args)).unwrap();
+        assert_eq!(config["crate_roots"].get("core").is_some(), include_corelib);
+
+        let config = &config["config"];
+        let settings = config["override"]
+            .as_object()
+            .unwrap()
+            .values()
+            .chain([&config["global"]]);
+        for settings in settings {
+            assert!(settings["dependencies"].get("core").is_none());
+        }
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn both_profiles_writes_a_config_per_profile() {
+    let dir = temp_dir();
+    let dev = Workspace::chain_at(&dir);
+    let mut release = Workspace::chain_at(&dir);
+    release.units[0]["cfg"] = json!([["target", "lib"], "release"]);
+    let mut metadata = release.metadata();
+    metadata["current_profile"] = json!("release");
+    let release_metadata = dir.join("release.json");
+    fs::write(&release_metadata, metadata.to_string()).unwrap();
+    let script = format!(
+        "if [ \"$SCARB_PROFILE\" = release ]; then cat {}; exit 0; fi",
+        release_metadata.display()
+    );
+    let scarb = fake_scarb(&dir, &dev, &script);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_scarb-eject"))
+        .args(["--package", "a", "--both-profiles", "--no-header"])
+        .current_dir(&dir)
+        .env("SCARB", &scarb)
+        .output()
+        .unwrap();
+    stdout(output);
+
+    let global_cfg = |profile: &str| {
+        let toml = fs::read_to_string(dir.join(format!("cairo_project.{profile}.toml"))).unwrap();
+        let toml: toml::Table = toml::from_str(&toml).unwrap();
+        toml["config"]["global"]["cfg_set"].to_string()
+    };
+    assert_eq!(global_cfg("dev"), r#"[["target", "lib"]]"#);
+    assert_eq!(global_cfg("release"), r#"["release", ["target", "lib"]]"#);
+    assert!(!dir.join("cairo_project.toml").exists());
+}
+
+#[test]
+fn overwrite_policies_against_missing_stale_and_current_files() {
+    let workspace = Workspace::chain();
+    let dir = temp_dir();
+    let output = dir.join("cairo_project.toml");
+    let eject_with = |policy: &str| {
+        let args = ["--package", "a", "-o", output.to_str().unwrap()];
+        run(
+            &workspace,
+            &[&args[..], &["--overwrite-policy", policy]].concat(),
+        )
+    };
+
+    stdout(eject_with("always"));
+    let current = fs::read_to_string(&output).unwrap();
+    assert!(current.contains("[crate_roots]"));
+    for policy in ["never", "if-changed"] {
+        fs::remove_file(&output).unwrap();
+        stdout(eject_with(policy));
+        assert_eq!(fs::read_to_string(&output).unwrap(), current);
+    }
+
+    for policy in ["always", "if-changed"] {
+        fs::write(&output, "stale").unwrap();
+        stdout(eject_with(policy));
+        assert_eq!(fs::read_to_string(&output).unwrap(), current);
+    }
+    fs::write(&output, "stale").unwrap();
+    let result = eject_with("never");
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr)
+        .contains("help: pass `--overwrite-policy always` to overwrite it"));
+    assert_eq!(fs::read_to_string(&output).unwrap(), "stale");
+
+    fs::write(&output, &current).unwrap();
+    let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 30);
+    fs::File::options()
+        .write(true)
+        .open(&output)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+    let result = eject_with("if-changed");
+    assert!(String::from_utf8_lossy(&result.stderr)
+        .contains(&format!("{} unchanged", output.display())));
+    stdout(result);
+    assert_eq!(fs::metadata(&output).unwrap().modified().unwrap(), past);
+}
+
+#[test]
+fn inspect_crate_prints_settings_of_present_crates_only() {
+    let workspace = Workspace::chain();
+    let settings = inspect(&workspace, "b", &[]);
+    assert_eq!(settings["name"], json!("b"));
+    assert_eq!(settings["edition"], json!("2024_07"));
+    assert_eq!(settings["version"], json!("0.1.0"));
+    assert_eq!(settings["cfg_set"], json!([["target", "lib"]]));
+    let dependencies = settings["dependencies"].as_object().unwrap();
+    assert_eq!(dependencies.keys().collect::<Vec<_>>(), ["c"]);
+
+    let toml = eject(&workspace, &["--inspect-crate", "b"]);
+    assert!(toml.starts_with("cfg_set = [[\"target\", \"lib\"]]\nedition = \"2024_07\"\n"));
+    assert!(toml.contains("\n[dependencies.c]\n"));
+
+    let output = run(&workspace, &["--package", "a", "--inspect-crate", "nope"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("crate `nope` is not a component of compilation unit a-lib"));
+}
+
+#[test]
+#[cfg(unix)]
+fn format_with_pipes_the_config_through_a_command() {
+    let workspace = Workspace::chain();
+    let plain = eject(&workspace, &["-o", "-"]);
+    assert_eq!(
+        eject(&workspace, &["-o", "-", "--format-with", "cat"]),
+        plain
+    );
+    assert_eq!(
+        eject(&workspace, &["-o", "-", "--format-with", "tr a-z A-Z"]),
+        plain.to_uppercase()
+    );
+
+    let output = temp_dir().join("cairo_project.toml");
+    for (formatter, error) in [
+        ("false", "formatter `false` failed with exit status: 1"),
+        (
+            "no-such-formatter",
+            "failed to run formatter `no-such-formatter`",
+        ),
+    ] {
+        let args = ["--package", "a", "-o", output.to_str().unwrap()];
+        let result = run(
+            &workspace,
+            &[&args[..], &["--format-with", formatter]].concat(),
+        );
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains(error));
+        assert!(!output.exists());
+    }
+}
+
+#[test]
+fn output_dir_holds_a_config_per_crate() {
+    let dir = temp_dir().join("configs");
+    let args = ["--output-dir", dir.to_str().unwrap()];
+    eject(&Workspace::chain(), &args);
+
+    let mut files = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(files, ["a.toml", "b.toml", "c.toml"]);
+    for name in ["a", "b", "c"] {
+        let toml = fs::read_to_string(dir.join(format!("{name}.toml"))).unwrap();
+        let toml: toml::Table = toml::from_str(&toml).unwrap();
+        let crate_roots = toml["crate_roots"].as_table().unwrap();
+        assert_eq!(crate_roots.keys().collect::<Vec<_>>(), [name]);
+    }
+
+    // Crates sharing a name would overwrite each other's file.
+    let mut workspace = Workspace::chain();
+    let components = workspace.units[0]["components_data"]
+        .as_array_mut()
+        .unwrap();
+    let mut other = components[2].clone();
+    other["id"] = json!("other-c");
+    components.push(other);
+    let dir = temp_dir().join("configs");
+    let output = run(
+        &workspace,
+        &["--package", "a", "--output-dir", dir.to_str().unwrap()],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("crates `c` and `other-c` are both named `c`"));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+}
@@ -0,0 +1,224 @@
+//! Builder of `scarb metadata --format-version 1` output, shared by unit and CLI tests.
+//!
+//! Every crate is its own package, named after it, with the package directory right under the
+//! workspace root. Components use their crate name as id, so it is also their key in
+//! `[crate_roots]` and `[config.override]`.
+
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+/// Package id of the corelib, which is never a workspace member.
+pub const CORE_ID: &str = "core 2.9.1 (std)";
+
+pub struct Workspace {
+    pub root: String,
+    pub packages: Vec<Value>,
+    pub units: Vec<Value>,
+    /// Extra keys of the `workspace` object, like `edition`.
+    pub extra: Map<String, Value>,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::at("/ws")
+    }
+}
+
+impl Workspace {
+    pub fn at(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().display().to_string(),
+            packages: Vec::new(),
+            units: Vec::new(),
+            extra: Map::new(),
+        }
+    }
+
+    /// Package id of the workspace member `name`.
+    pub fn id(&self, name: &str) -> String {
+        if name == "core" {
+            return CORE_ID.to_owned();
+        }
+        format!("{name} 0.1.0 (path+file://{}/{name})", self.root)
+    }
+
+    /// Directory of the package with the crate `name`.
+    pub fn dir(&self, name: &str) -> String {
+        if name == "core" {
+            return "/corelib".to_owned();
+        }
+        format!("{}/{name}", self.root)
+    }
+
+    /// Add a workspace member of edition `2024_07` depending on packages in `dependencies`.
+    pub fn package(&mut self, name: &str, dependencies: &[&str]) -> &mut Value {
+        let dependencies = dependencies
+            .iter()
+            .map(|dependency| {
+                json!({
+                    "name": dependency,
+                    "version_req": "*",
+                    "source": format!("path+file://{}", self.dir(dependency)),
+                    "kind": null,
+                })
+            })
+            .collect::<Vec<_>>();
+        self.packages.push(json!({
+            "id": self.id(name),
+            "name": name,
+            "version": "0.1.0",
+            "edition": "2024_07",
+            "source": format!("path+file://{}", self.dir(name)),
+            "manifest_path": format!("{}/Scarb.toml", self.dir(name)),
+            "root": self.dir(name),
+            "dependencies": dependencies,
+            "targets": [],
+            "experimental_features": [],
+        }));
+        self.packages.last_mut().unwrap()
+    }
+
+    /// Add a compilation unit of `package` for a target whose kind and name are both `target`.
+    ///
+    /// `components` are crate names with the crate names they depend on.
+    pub fn unit(
+        &mut self,
+        package: &str,
+        target: &str,
+        components: &[(&str, &[&str])],
+    ) -> &mut Value {
+        let cfg = json!([["target", target]]);
+        let components = components
+            .iter()
+            .map(|(name, dependencies)| {
+                json!({
+                    "package": self.id(name),
+                    "name": name,
+                    "source_path": format!("{}/src/lib.cairo", self.dir(name)),
+                    "id": name,
+                    "discriminator": null,
+                    "cfg": cfg,
+                    "dependencies": dependencies
+                        .iter()
+                        .map(|id| json!({ "id": id }))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+        self.units.push(json!({
+            "id": format!("{package}-{target}"),
+            "package": self.id(package),
+            "target": {
+                "kind": target,
+                "name": target,
+                "source_path": format!("{}/src/lib.cairo", self.dir(package)),
+                "params": {},
+            },
+            "compiler_config": {},
+            "components_data": components,
+            "cfg": cfg,
+        }));
+        self.units.last_mut().unwrap()
+    }
+
+    /// A workspace with members `a`, `b` and `c`, where `a` depends on `b` and `b` on `c`, and
+    /// a single `lib` unit of `a` with the corelib.
+    pub fn chain() -> Self {
+        Self::default().with_chain()
+    }
+
+    /// [`Workspace::chain`] with the root at `root`.
+    pub fn chain_at(root: impl AsRef<Path>) -> Self {
+        Self::at(root).with_chain()
+    }
+
+    fn with_chain(mut self) -> Self {
+        self.package("a", &["b"]);
+        self.package("b", &["c"]);
+        self.package("c", &[]);
+        self.unit(
+            "a",
+            "lib",
+            &[
+                ("a", &["b", "core"]),
+                ("b", &["c", "core"]),
+                ("c", &["core"]),
+                ("core", &[]),
+            ],
+        );
+        self
+    }
+
+    /// The metadata JSON, with the corelib package added if any unit uses it.
+    pub fn metadata(&self) -> Value {
+        let mut packages = self.packages.clone();
+        let uses_core = self.units.iter().any(|unit| {
+            unit["components_data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|c| c["name"] == "core")
+        });
+        if uses_core && !packages.iter().any(|p| p["id"] == CORE_ID) {
+            packages.push(json!({
+                "id": CORE_ID,
+                "name": "core",
+                "version": "2.9.1",
+                "edition": "2024_07",
+                "source": "std",
+                "manifest_path": "/corelib/Scarb.toml",
+                "root": "/corelib",
+                "dependencies": [],
+                "targets": [],
+                "experimental_features": [],
+            }));
+        }
+
+        let members = self
+            .packages
+            .iter()
+            .map(|p| p["id"].clone())
+            .collect::<Vec<_>>();
+        let mut workspace = json!({
+            "manifest_path": format!("{}/Scarb.toml", self.root),
+            "root": self.root,
+            "members": members,
+        });
+        workspace
+            .as_object_mut()
+            .unwrap()
+            .extend(self.extra.clone());
+
+        json!({
+            "version": 1,
+            "app_exe": null,
+            "app_version_info": {
+                "version": "2.9.1",
+                "commit_info": null,
+                "cairo": { "version": "2.9.1", "commit_info": null },
+            },
+            "target_dir": format!("{}/target", self.root),
+            "workspace": workspace,
+            "packages": packages,
+            "compilation_units": self.units,
+            "current_profile": "dev",
+            "profiles": ["dev", "release"],
+        })
+    }
+
+    pub fn parse(&self) -> scarb_metadata::Metadata {
+        serde_json::from_value(self.metadata()).expect("fixture must be valid metadata")
+    }
+
+    /// The package metadata of the crate `name`.
+    pub fn get(&self, name: &str) -> scarb_metadata::PackageMetadata {
+        self.parse()
+            .packages
+            .into_iter()
+            .find(|p| p.name == name)
+            .expect("fixture must have the package")
+    }
+}